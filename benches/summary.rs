@@ -0,0 +1,60 @@
+//! Times `show summary` on a synthetic summary of one million rows. The table is printed on stderr, so run it with
+//! `cargo bench --bench summary 2>/dev/null`
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use whitesmith::tools::summary::{print_summary, print_summary_file, ColumnWidths, SummaryOptions};
+
+const ROWS: usize = 1_000_000;
+
+fn main() {
+    let path = std::env::temp_dir().join(format!("whitesmith-summary-bench-{}.csv", std::process::id()));
+    write_summary(&path);
+
+    let cases = vec![
+        ("sorted by time", options(Some("~time"), ColumnWidths::Measured), false),
+        ("in memory", options(None, ColumnWidths::Measured), false),
+        ("streamed", options(None, ColumnWidths::Measured), true),
+        ("streamed, sampled widths", options(None, ColumnWidths::Sampled(1000)), true),
+    ];
+    for (name, options, streamed) in &cases {
+        let start = Instant::now();
+        if *streamed {
+            print_summary_file(&path, options)
+        } else {
+            print_summary(&mut BufReader::new(File::open(&path).unwrap()), options)
+        }.expect("Cannot print the summary");
+        println!("{:<26} {:>8.2?}", name, start.elapsed());
+    }
+
+    fs::remove_file(&path).unwrap();
+}
+
+fn options(sort: Option<&str>, widths: ColumnWidths) -> SummaryOptions {
+    SummaryOptions {
+        sort_columns: sort.map(|key| vec![key.parse().unwrap()]),
+        filters: Vec::new(),
+        columns: None,
+        stats: false,
+        head: None,
+        tail: None,
+        color: false,
+        widths,
+    }
+}
+
+/// A summary with the columns of a run, where one computation in a hundred fails
+fn write_summary(path: &Path) {
+    let mut file = BufWriter::new(File::create(path).unwrap());
+    writeln!(file, "name\tstatus\ttime\tuser_time\tsystem_time\titeration\tstarted_at\thostname\tthreads\tcores\tmeasure_mode\tlog\tpartial_result").unwrap();
+    for row in 0..ROWS {
+        let status = if row % 100 == 0 { "Error" } else { "Ok" };
+        let time = humantime::format_duration(Duration::from_millis((row * 7919 % 100_000) as u64));
+        writeln!(
+            file,
+            "fuzz_{}\t{}\t{}\t{}\t0s\t1/1\t2024-06-01T12:00:00+02:00\tnode-{}\t8\t64\tthroughput\tfuzz_{}/fuzz_{}_1.err\t{}",
+            row, status, time, time, row % 16, row, row, row % 1000,
+        ).unwrap();
+    }
+}
@@ -0,0 +1,46 @@
+//! whitesmith fetches, builds and runs the computations of a configuration, then archives their results.
+//! The `whitesmith` command line is a thin layer over this crate, which can drive the same actions from Rust:
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use whitesmith::{lock_project, open_project, run, zip_project, LockOptions, RunOptions, RunState, ZipOptions};
+//!
+//! let mut project = open_project("bench.ron".as_ref());
+//! project.fetch_sources(4, false);
+//! project.build(false);
+//! project.nb_threads = Some(8);
+//! let lock = lock_project(&project, &LockOptions::default())?;
+//! let project = Arc::new(project);
+//! let report = run(project.clone(), &RunOptions::default(), &Arc::new(RunState::default()))?;
+//! drop(lock);
+//! for (name, result) in report.failures() {
+//!     eprintln!("{}: {}", name, result);
+//! }
+//! zip_project("bench.zip".as_ref(), &project, &ZipOptions::default())?;
+//! # Ok::<(), String>(())
+//! ```
+//!
+//! The actions log through the `log` crate. `lock_project`, `run` and the archive functions return why they failed
+//! instead of exiting the process, the command line decides how to exit.
+
+extern crate wait_timeout;
+extern crate serde;
+extern crate ron;
+extern crate humantime;
+extern crate clap;
+
+pub mod model;
+pub mod tools;
+
+use std::sync::{Arc, Mutex};
+use once_cell::sync::Lazy;
+
+pub use crate::model::computation_result::{ComputationResult, CpuTime, RunReport};
+pub use crate::model::configuration::{open_project, write_effective_configuration};
+pub use crate::model::job::cmd_env::Status;
+pub use crate::model::lock::LockOptions;
+pub use crate::model::project::{MeasureMode, Project};
+pub use crate::model::run::{lock_project, run, RunOptions, RunState};
+pub use crate::tools::archive::{append_to_archive, extract_project, zip_project, Compression, ZipOptions};
+
+pub static NO_WRAP: Lazy<Arc<Mutex<bool>>> = Lazy::new(|| Arc::new(Mutex::new(false)));
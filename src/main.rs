@@ -1,32 +1,49 @@
-mod model;
-mod tools;
-
 use std::fs::File;
-use std::io::{BufReader, BufRead, stdout, Write, stdin, BufWriter, Seek};
+use std::io::{BufReader, BufRead, stdout, Write, stdin, IsTerminal, ErrorKind};
 use std::path::{Path, PathBuf};
-
-use crate::model::project::{Project, ProjectVersionOnly};
-use crate::model::{working_directory, source_directory, log_directory, summary_file, zip_file};
-use std::sync::{Arc, Mutex};
-use crate::tools::RecursiveZipWriter;
-use zip::CompressionMethod;
-use ron::ser::PrettyConfig;
 use std::ffi::OsStr;
-use std::collections::HashSet;
-use crate::model::commands::{kill, restore_path};
-use termimad::MadSkin;
-use std::cmp::Ordering;
-use clap::{Parser, Subcommand};
-use once_cell::sync::Lazy;
-use termimad::crossterm::style::Color;
-use threadpool::ThreadPool;
-use crate::model::version::Version;
-
-extern crate wait_timeout;
-extern crate serde;
-extern crate ron;
-extern crate humantime;
-extern crate clap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use clap::{Args, Parser, Subcommand};
+use log::{debug, error, info, warn, Level};
+use whitesmith::{append_to_archive, extract_project, lock_project, run, write_effective_configuration, zip_project, LockOptions, RunOptions, RunState, ZipOptions, NO_WRAP};
+use whitesmith::model::project::{MeasureMode, Project, ProjectVersionOnly, Verbosity};
+use whitesmith::model::aliases::{Alias, Override};
+use whitesmith::model::configuration::{
+    is_zip_archive, locate_configuration, parse_configuration, parse_project, place_project, read_configuration, read_other_summary,
+    read_summary, resolve_project, write_migrated_configuration, RESERVED_ALIASES,
+};
+use whitesmith::model::layout::TemporaryDirectory;
+use whitesmith::model::job::cmd_env::PrefixStyle;
+use whitesmith::model::layout::Layout;
+use whitesmith::model::{temporary_working_directory, summary_file, zip_file, backup_file};
+use whitesmith::model::run::write_junit;
+#[cfg(unix)]
+use whitesmith::model::commands::send_signal;
+use whitesmith::tools::available_cores;
+use whitesmith::tools::archive::read_zip_entry;
+use whitesmith::tools::notes::Theme;
+use whitesmith::tools::summary::{print_summary_file, watch_summary, ColumnWidths, Filter, SortKey, SummaryOptions};
+use whitesmith::tools::compare::print_comparison;
+use whitesmith::tools::notify::NotifyOn;
+use whitesmith::tools::merge::{merge, Archive, OnConflict};
+use whitesmith::model::migration;
+use whitesmith::model::shard::{Shard, ShardMode};
+use whitesmith::model::version::Version;
+
+/// Diagnostics go to stderr, the informative messages without any decoration so that they read as before
+fn init_logger(verbosity: Verbosity) {
+    env_logger::Builder::new()
+        .filter_level(verbosity.level_filter())
+        .parse_env(env_logger::Env::new().filter("WHITESMITH_LOG"))
+        .format(|buffer, record| match record.level() {
+            Level::Info => writeln!(buffer, "{}", record.args()),
+            Level::Error => writeln!(buffer, "error: {}", record.args()),
+            Level::Warn => writeln!(buffer, "warning: {}", record.args()),
+            level => writeln!(buffer, "[{}] {}", level.as_str().to_lowercase(), record.args()),
+        })
+        .init();
+}
 
 fn parse_duration(v: &str) -> Result<humantime::Duration, String> {
     if let Ok(duration) = v.parse::<humantime::Duration>() {
@@ -37,12 +54,36 @@ fn parse_duration(v: &str) -> Result<humantime::Duration, String> {
 }
 
 #[derive(Parser)]
-struct CLI {
+struct Cli {
+    /// Configuration file (.ron, .toml, .yaml), results archive (.zip) or directory containing a configuration.ron or whitesmith.ron file
     path: PathBuf,
     #[clap(subcommand)]
     action: Action,
+    /// Debug mode:
+    /// print debug diagnostics (full build and clean command lines, variables set for and working directory of
+    /// every computation, timings of the spawn, exit and logs of every computation, and of the whole action),
+    /// print the stderr of a failing computation and stop its iterations without marking it done,
+    /// and keep the logs of the failed computations relaunched by `run --with-failure` (`<name>.failed-<time>`)
     #[arg(long)]
     debug: bool,
+    /// Only print the warnings and the errors, `--debug` takes precedence. `WHITESMITH_LOG` overrides both
+    #[arg(short, long)]
+    quiet: bool,
+    /// Print the command lines on a single line instead of wrapping them at the width of the terminal
+    #[arg(long)]
+    no_wrap: bool,
+    /// Never print colors, same as setting NO_COLOR
+    #[arg(long)]
+    no_color: bool,
+    /// Put the working directory in ROOT (the temp directory of the system by default), under a name made of the name
+    /// and the version of the configuration, so that the following commands find it. It is deleted once the results
+    /// are archived by `zip` or `clean`. The sources, the logs and the summary go with it unless the `layout` of the
+    /// configuration relocates them
+    #[arg(long, value_name = "ROOT", require_equals = true)]
+    temp_dir: Option<Option<PathBuf>>,
+    /// Keep the working directory created by `--temp-dir`
+    #[arg(long, requires = "temp_dir")]
+    keep: bool,
 }
 
 #[derive(Subcommand)]
@@ -52,53 +93,178 @@ enum Action {
     Run(Run),
     Clean(Clean),
     Zip(Zip),
+    /// Check the configuration without running anything, exits with 1 if a problem is found
+    Validate(Validate),
+    /// Upgrade the configuration file to the current version, the original file is kept with a .bak extension
+    Migrate,
+    /// Unpack a results archive next to a configuration file, so that it can be inspected again
+    Extract(Extract),
     Show(Show),
+    /// Compare the results of PATH with the ones of another configuration or archive, exits with 1 if a computation regressed
+    Compare(Compare),
+    /// Merge the results of PATH with the ones of other archives, e.g. the shards of a run made on several machines
+    Merge(Merge),
 }
 
 #[derive(Parser)]
 struct Fetch {
     #[arg(short, long)]
     commit: Option<String>,
+    /// Number of repositories and submodules fetched in parallel (defaults to the number of logical CPUs)
+    #[arg(short, long)]
+    jobs: Option<usize>,
+    /// Wipe the source directory and clone again instead of updating the existing clone
+    #[arg(long)]
+    force: bool,
 }
 
 #[derive(Parser)]
 struct Run {
-    #[arg(short, long)]
-    configuration: Option<PathBuf>,
-    #[arg(short, long)]
-    overrides: Vec<String>,
+    #[command(flatten)]
+    overrides: OverrideOptions,
     #[arg(long)]
     with_failure: bool,
     #[arg(long)]
     with_in_progress: bool,
     #[arg(long)]
     with_timeout: bool,
+    /// Number of computations run in parallel (defaults to the `nb_threads` of the configuration, then to the number of logical CPUs)
     #[arg(short, long)]
     nb_threads: Option<usize>,
     #[arg(short, long, value_parser = parse_duration)]
     global_timeout: Option<humantime::Duration>,
     #[arg(long)]
     only: Option<Vec<String>>,
+    /// Write times as raw seconds instead of human-readable durations
+    #[arg(long)]
+    raw_seconds: bool,
+    /// Also stop (SIGSTOP) the running computations when the run is paused with SIGTSTP
+    #[arg(long)]
+    pause_children: bool,
+    /// Also print the output of the computations, each line prefixed with the computation name
+    #[arg(long)]
+    tee: bool,
+    /// Prefix the lines printed for a computation with its name, in color by default
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "color")]
+    prefix: Option<PrefixStyle>,
+    #[command(flatten)]
+    lock: LockOptions,
+    /// Only run the i-th of n buckets of computations (e.g. `--shard 2/4`), the results can then be merged
+    #[arg(long)]
+    shard: Option<Shard>,
+    /// How the computations are distributed over the shards
+    #[arg(long, value_enum, default_value = "striped", requires = "shard")]
+    shard_mode: ShardMode,
+    /// Shuffle the computations with this seed before distributing them over the shards
+    #[arg(long, requires = "shard")]
+    shard_seed: Option<u64>,
+    /// Write Prometheus gauges about the run in this file once it ends, e.g. for the textfile collector of node_exporter
+    #[arg(long)]
+    metrics_file: Option<PathBuf>,
+    /// Runs after which the `notify_url` of the configuration is called
+    #[arg(long, value_enum, default_value = "always")]
+    notify_on: NotifyOn,
+    /// Write the summary as a JUnit XML report in this file once the run ends, for CI servers
+    #[arg(long)]
+    junit: Option<PathBuf>,
+    /// Run one computation at a time for clean timings (`isolated`, whatever the number of threads)
+    /// or in parallel (`throughput`). Defaults to the `measure_mode` of the configuration
+    #[arg(long, value_enum)]
+    measure_mode: Option<MeasureMode>,
 }
 
 #[derive(Parser)]
 struct Build {
+    #[command(flatten)]
+    overrides: OverrideOptions,
+    /// Build even if the sources and the build command did not change since the last build
+    #[arg(long)]
+    force: bool,
+    #[command(flatten)]
+    lock: LockOptions,
+}
+
+// Sources of the alias overrides, applied in this order so that the last ones take precedence
+#[derive(Args)]
+struct OverrideOptions {
     #[arg(short, long)]
     configuration: Option<PathBuf>,
+    /// File of KEY=VALUE lines, `-` reads them from stdin
+    #[arg(long)]
+    overrides_file: Option<PathBuf>,
     #[arg(short, long)]
-    overrides: Vec<String>,
+    overrides: Vec<Override>,
+}
+
+#[derive(Parser)]
+struct Validate {
+    #[command(flatten)]
+    overrides: OverrideOptions,
 }
 
 #[derive(Parser)]
 struct Clean {
+    #[command(flatten)]
+    zip: ZipOptions,
+    /// Only clean the given computations, keeping the results of the other ones
+    #[arg(long)]
+    only: Option<Vec<String>>,
+    /// Only clean the computations that failed
+    #[arg(long)]
+    with_failure: bool,
+    /// Only clean the computations that timed out
+    #[arg(long)]
+    with_timeout: bool,
+    /// Do not ask before cleaning, the previous results are saved unless --no-backup is given
     #[arg(short, long)]
-    zip_with: Vec<PathBuf>,
+    yes: bool,
+    /// Never read stdin, same as --yes (also enabled by the CI environment variable)
+    #[arg(long)]
+    no_input: bool,
+    /// Do not save the previous results before cleaning
+    #[arg(long)]
+    no_backup: bool,
+    #[command(flatten)]
+    lock: LockOptions,
 }
 
 #[derive(Parser)]
 struct Zip {
+    #[command(flatten)]
+    options: ZipOptions,
+    /// Add the zip_with files to this existing archive instead of creating a new one
+    #[arg(long, value_name = "ARCHIVE")]
+    append: Option<PathBuf>,
+}
+
+#[derive(Parser)]
+struct Compare {
+    /// Configuration file, results archive (.zip) or directory whose results are compared with the ones of PATH
+    other: PathBuf,
+    /// Slowdown, in percent, from which a computation is reported as a regression
+    #[arg(long, default_value_t = 10.0)]
+    threshold: f64,
+}
+
+#[derive(Parser)]
+struct Merge {
+    /// Other results archives (.zip), their results take precedence over the ones of PATH
+    #[arg(required = true)]
+    archives: Vec<PathBuf>,
+    /// Merged archive, or merged summary if it ends with .csv (defaults to `<name>.merged.zip` next to PATH)
     #[arg(short, long)]
-    zip_with: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    /// Also copy the logs of the merged computations
+    #[arg(long)]
+    with_logs: bool,
+    /// What to do when several archives hold results for the same computation
+    #[arg(long, value_enum, default_value = "last")]
+    on_conflict: OnConflict,
+}
+
+#[derive(Parser)]
+struct Extract {
+    directory: PathBuf,
 }
 
 #[derive(Parser)]
@@ -109,16 +275,62 @@ struct Show {
 
 #[derive(Subcommand)]
 enum ShowAction {
-    Notes,
+    Notes(Notes),
     Summary(Summary),
     Status(Status),
+    /// List the computations with their command, timeout and status
+    List(Status),
+    /// Print the script a computation runs once the aliases are restored, without running it
+    Command(ShowCommand),
     Json(Json),
+    /// Print the version of the configuration and of the whitesmith that archived the results
+    Version,
+}
+
+#[derive(Parser)]
+struct Notes {
+    /// Print the raw Markdown, which is the default when stdout is not a terminal
+    #[arg(long)]
+    plain: bool,
+    /// Colors of the notes, defaults to the `notes_theme` of the configuration
+    #[arg(long, value_enum)]
+    theme: Option<Theme>,
 }
 
 #[derive(Parser)]
 struct Summary {
+    /// Sort the rows by [~]COLUMN[:human|num|lex], `~` reverses the order. Human sorting (the default) compares
+    /// the numbers within the cells by value, `num` the cells as numbers or durations, `lex` byte by byte
     #[arg(short, long)]
-    sort: Option<Vec<String>>,
+    sort: Option<Vec<SortKey>>,
+    /// Keep only the rows matching COLUMN=VALUE, COLUMN!=VALUE, COLUMN<VALUE or COLUMN>VALUE
+    #[arg(short, long)]
+    filter: Vec<Filter>,
+    /// Only display the given columns, in the given order
+    #[arg(short, long, value_delimiter = ',')]
+    columns: Option<Vec<String>>,
+    /// Append min/max/mean/median/sum of the numeric columns
+    #[arg(long)]
+    stats: bool,
+    /// Only display the first N rows once sorted and filtered, e.g. `--sort ~time --head 10` for the ten slowest
+    #[arg(long, value_name = "N", conflicts_with = "tail")]
+    head: Option<usize>,
+    /// Only display the last N rows once sorted and filtered
+    #[arg(long, value_name = "N")]
+    tail: Option<usize>,
+    /// Pad the columns to at least N characters instead of measuring them, so that a big summary is printed
+    /// as it is read. The longer cells are not aligned
+    #[arg(long, value_name = "N", conflicts_with = "sample_widths")]
+    assume_width: Option<usize>,
+    /// Measure the columns on the first N rows only, so that a big summary is printed as it is read
+    #[arg(long, value_name = "N")]
+    sample_widths: Option<usize>,
+    /// Redraw the summary every INTERVAL (2s by default) until interrupted, e.g. while a run is in progress
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_duration)]
+    watch: Option<Option<humantime::Duration>>,
+    /// Also write the summary as a JUnit XML report in this file, for CI servers
+    #[arg(long)]
+    junit: Option<PathBuf>,
 }
 
 #[derive(Parser)]
@@ -127,184 +339,325 @@ struct Status {
     only: Option<Vec<String>>,
 }
 
+#[derive(Parser)]
+struct ShowCommand {
+    name: String,
+}
+
 #[derive(Parser)]
 struct Json {
     #[arg(short, long)]
     pretty: bool,
 }
 
-fn configure(path: &PathBuf, project: &mut Project) {
-    let file = File::open(path)
-        .expect(&format!("Cannot open configuration file {:?}", path));
+fn configure(path: &PathBuf, project: &mut Project, separator: char) {
+    let reader: Box<dyn BufRead> = if path == Path::new("-") {
+        Box::new(stdin().lock())
+    } else {
+        let file = File::open(path)
+            .unwrap_or_else(|_| panic!("Cannot open configuration file {:?}", path));
+        Box::new(BufReader::new(file))
+    };
 
-    let reader = BufReader::new(file);
-    for line in reader.lines() {
+    for (i, line) in reader.lines().enumerate() {
         let line = line.unwrap();
-        let fields = line.split(':').collect::<Vec<_>>();
-        let (key, value) = (fields[0], fields[1]);
-        project.aliases.insert(key.to_owned(), value.to_owned().parse().unwrap());
+        if line.trim().is_empty() {
+            continue;
+        }
+        match Override::parse(&line, separator) {
+            Ok(Override { key, value }) => override_alias(project, key, value),
+            Err(e) => {
+                error!("{}:{}: {}", path.display(), i + 1, e);
+                std::process::exit(1);
+            }
+        }
     }
 }
 
-pub static ABORT: Lazy<Arc<Mutex<bool>>> = Lazy::new(|| Arc::new(Mutex::new(false)));
-pub static CHILDREN: Lazy<Arc<Mutex<HashSet<u32>>>> = Lazy::new(|| Arc::new(Mutex::new(HashSet::new())));
+const ALIAS_ENV_PREFIX: &str = "WS_ALIAS_";
 
-const ACCEPTED_VERSIONS: [Version; 4] = [
-    Version(0, 5, 0),
-    Version(0, 6, 0),
-    Version(0, 6, 1),
-    Version(0, 6, 2),
-];
+/// Applies the configuration file, the overrides file, the `WS_ALIAS_<KEY>` environment variables
+/// then the inline overrides.
+fn apply_overrides(project: &mut Project, options: &OverrideOptions) {
+    if let Some(path) = &options.configuration {
+        configure(path, project, ':');
+    }
+    if let Some(path) = &options.overrides_file {
+        configure(path, project, '=');
+    }
+    for (key, value) in std::env::vars_os() {
+        let key = key.to_string_lossy();
+        if let Some(key) = key.strip_prefix(ALIAS_ENV_PREFIX) {
+            let value = match value.into_string() {
+                Ok(value) => value,
+                Err(_) => {
+                    warn!("Ignoring {}{}: its value is not valid UTF-8", ALIAS_ENV_PREFIX, key);
+                    continue;
+                }
+            };
+            match Override::parse(&format!("{}={}", key, value), '=') {
+                Ok(Override { key, value }) => override_alias(project, key, value),
+                Err(e) => warn!("Ignoring {}{}: {}", ALIAS_ENV_PREFIX, key, e),
+            }
+        }
+    }
+    for Override { key, value } in &options.overrides {
+        override_alias(project, key.clone(), value.clone());
+    }
+}
 
+fn override_alias(project: &mut Project, key: String, value: Alias) {
+    if RESERVED_ALIASES.contains(&key.as_str()) {
+        warn!("Ignoring the override of {}: it is a reserved alias", key);
+    } else {
+        project.aliases.insert(key, value);
+    }
+}
 
 fn main() {
-    let CLI { path, action, debug } = CLI::parse();
-    assert!(path.extension() == Some(OsStr::new("zip")) || path.extension() == Some(OsStr::new("ron")));
-
-    let mut config_file = File::open(&path)
-        .expect(&format!("Cannot open the configuration file '{:?}'. Maybe the file doesn't exists or the permissions are too restrictive.", path));
-
-    let version = if path.extension() == Some(OsStr::new("zip")) {
-        let mut archive = zip::ZipArchive::new(&mut config_file)
-            .expect("Cannot read the zip file");
-        let mut zip_config_file = archive.by_name("configuration.ron")
-            .expect("Cannot read the configuration.ron file. Maybe the archive wasn't build by whitesmith");
-        ron::de::from_reader::<_, ProjectVersionOnly>(BufReader::new(&mut zip_config_file))
-             .map_err(|e| e.to_string())
-             .expect("Cannot parse the configuration file")
+    let Cli { path, action, debug, quiet, no_wrap, no_color, temp_dir, keep } = Cli::parse();
+    *NO_WRAP.lock().unwrap() = no_wrap;
+    let verbosity = if debug {
+        Verbosity::Debug
+    } else if quiet {
+        Verbosity::Quiet
     } else {
-        ron::de::from_reader::<_, ProjectVersionOnly>(BufReader::new(&mut config_file))
-             .map_err(|e| e.to_string())
-             .expect("Cannot parse the configuration file")
+        Verbosity::Normal
     };
-    config_file.rewind().unwrap();
+    init_logger(verbosity);
+    if no_color {
+        colored::control::set_override(false);
+    }
+    let start = Instant::now();
+
+    let path = locate_configuration(&path);
+    let is_zip_archive = is_zip_archive(&path);
+    let (configuration, format) = read_configuration(&path, is_zip_archive);
+
+    let version = parse_configuration::<ProjectVersionOnly>(&path, &configuration, format);
+    if !migration::is_supported(&version.version) {
+        let message = format!("{} is not accepted by the current whitesmith instance. Supported versions are {}", &version.version, migration::supported_range());
+        if let Action::Validate(_) = action {
+            error!("The configuration is invalid: {}", message);
+            std::process::exit(1);
+        }
+        panic!("{}", message);
+    }
 
-    if !ACCEPTED_VERSIONS.contains(&version.version) {
-        panic!("{:?} is not accepted by the current whitesmith instance. Valid versions are: {:?}", &version.version, &ACCEPTED_VERSIONS.map(|it| it.to_string()));
+    let (mut project, migrated_from) = parse_project(&path, &configuration, format);
+    let migrated = migrated_from.is_some();
+    let previous_version = migrated_from.unwrap_or_else(|| project.version.clone());
+    if let Action::Migrate = action {
+        if !migrated {
+            info!("The configuration already uses version {}", project.version);
+        } else if is_zip_archive {
+            error!("A zip archive cannot be migrated in place");
+            std::process::exit(1);
+        } else {
+            write_migrated_configuration(&path, &project, format);
+            info!("The configuration has been migrated from {} to {}", previous_version, project.version);
+        }
+        return;
+    } else if migrated {
+        warn!(
+            "The configuration uses version {} and has been migrated to {} in memory. Run `whitesmith {} migrate` to upgrade the file.",
+            previous_version, project.version, path.display()
+        );
+    }
+    // An archive of a newer whitesmith may hold files or columns this one does not know about
+    let producer = project.whitesmith_version.as_deref().and_then(|it| it.parse::<Version>().ok());
+    if let (Some(producer), Ok(current)) = (producer, env!("CARGO_PKG_VERSION").parse::<Version>()) {
+        if producer > current {
+            warn!("The archive was built by whitesmith {}, which is newer than this one ({})", producer, current);
+        }
     }
 
-    let (mut project, is_zip_archive) = if path.extension() == Some(OsStr::new("zip")) {
-        let mut archive = zip::ZipArchive::new(config_file)
-            .expect("Cannot read the zip file");
-        let zip_config_file = archive.by_name("configuration.ron")
-            .expect("Cannot read the configuration.ron file. Maybe the archive wasn't build by whitesmith");
-        (ron::de::from_reader::<_, Project>(BufReader::new(zip_config_file))
-             .map_err(|e| e.to_string())
-             .expect("Cannot parse the configuration file"), true)
-    } else {
-        (ron::de::from_reader::<_, Project>(BufReader::new(config_file))
-             .map_err(|e| e.to_string())
-             .expect("Cannot parse the configuration file"), false)
+    resolve_project(&mut project, &path);
+    let temporary_directory = match temp_dir.filter(|_| !is_zip_archive) {
+        Some(_) if project.layout.working_directory.is_some() => {
+            warn!("Ignoring --temp-dir, the layout of the configuration sets the working directory");
+            None
+        }
+        Some(root) => {
+            let root = root.unwrap_or_else(std::env::temp_dir);
+            let directory = temporary_working_directory(&root, &path, &project);
+            info!("Working directory: {}", directory);
+            project.layout.working_directory = Some(directory.clone());
+            if keep { None } else { Some(TemporaryDirectory { path: PathBuf::from(directory) }) }
+        }
+        None => None,
     };
-
-    project.working_directory = working_directory(&path, &project.versioning);
-    project.source_directory = source_directory(&path, &project.versioning);
-    project.log_directory = log_directory(&path, &project.versioning);
-    project.summary_file = summary_file(&path, &project.versioning, is_zip_archive);
+    place_project(&mut project, &path);
     project.debug = debug;
-
-    project.aliases.insert(String::from("PROJECT"), project.working_directory.to_owned().parse().unwrap());
-    project.aliases.insert(String::from("SOURCES"), project.source_directory.to_owned().parse().unwrap());
-    project.aliases.insert(String::from("LOGS"), project.log_directory.to_owned().parse().unwrap());
-    project.aliases.insert(String::from("SUMMARY_FILE"), project.summary_file.to_owned().parse().unwrap());
-
-    project.init();
+    project.verbosity = verbosity;
 
     let zip_path = zip_file(&path, &project);
 
+    debug!("Configuration loaded in {:?}", start.elapsed());
+    // Whether the results are archived, or deleted on purpose, so that the temporary working directory can go
+    let mut archived = false;
     match action {
         Action::Fetch(fetch_args) => {
             if let Some(commit) = fetch_args.commit {
                 project.versioning.commit = Some(commit);
             }
-            project.fetch_sources();
+            let jobs = fetch_args.jobs
+                .unwrap_or_else(available_cores);
+            project.fetch_sources(jobs, fetch_args.force);
         }
         Action::Build(build_args) => {
-            if let Some(path) = build_args.configuration {
-                configure(&path, &mut project);
-            }
-            for _override in build_args.overrides {
-                let fields = _override.split(':').collect::<Vec<_>>();
-                let (key, value) = (fields[0], fields[1]);
-                project.aliases.insert(key.to_owned(), value.to_owned().parse().unwrap());
-            }
-            project.build();
+            apply_overrides(&mut project, &build_args.overrides);
+            let _lock = or_exit(lock_project(&project, &build_args.lock));
+            write_effective_configuration(&project, "last_build_configuration.ron");
+            project.build(build_args.force);
         }
         Action::Run(run_args) => {
-            if let Some(path) = run_args.configuration {
-                configure(&path, &mut project);
-            }
-
-            for _override in run_args.overrides {
-                let fields = _override.split(':').collect::<Vec<_>>();
-                let (key, value) = (fields[0], fields[1]);
-                project.aliases.insert(key.to_owned(), value.to_owned().parse().unwrap());
-            }
+            apply_overrides(&mut project, &run_args.overrides);
             if let Some(duration) = run_args.global_timeout {
                 project.global_timeout = Some(duration.into());
             }
-            if let Ok(file) = File::create(Path::new(&project.working_directory).join("last_running_configuration.ron")) {
-                let writer = BufWriter::new(file);
-                ron::ser::to_writer_pretty(writer, &project, PrettyConfig::default())
-                    .expect("Cannot serialize the project file to toml");
+            if run_args.raw_seconds {
+                project.raw_seconds = true;
+            }
+            project.tee = run_args.tee;
+            project.prefix = run_args.prefix;
+            project.notify_on = run_args.notify_on;
+            if let Some(shard) = run_args.shard {
+                let names = project.computation_names();
+                let selection = shard.select(names.iter().cloned(), run_args.shard_mode, run_args.shard_seed);
+                info!("Shard {}/{}: {} of the {} computations", shard.index, shard.count, selection.len(), names.len());
+                project.selection = Some(selection);
+            }
+            // Recorded in the summary and in the last running configuration
+            if let Some(measure_mode) = run_args.measure_mode {
+                project.measure_mode = measure_mode;
             }
+            let nb_threads = run_args.nb_threads.or(project.nb_threads).unwrap_or_else(available_cores);
+            project.nb_threads = match project.measure_mode {
+                MeasureMode::Isolated if nb_threads > 1 => {
+                    info!("Running one computation at a time in the isolated measure mode, instead of {}", nb_threads);
+                    Some(1)
+                }
+                _ => Some(nb_threads),
+            };
+            let lock = or_exit(lock_project(&project, &run_args.lock));
+            write_effective_configuration(&project, "last_running_configuration.ron");
             let project = Arc::new(project);
-            run_project(
-                project.clone(),
-                run_args.nb_threads,
-                run_args.with_in_progress,
-                run_args.with_timeout,
-                run_args.with_failure,
-            );
+            let state = Arc::new(RunState::default());
+            handle_signals(&state, run_args.pause_children);
+            let result = run(project.clone(), &RunOptions {
+                with_in_progress: run_args.with_in_progress,
+                with_timeout: run_args.with_timeout,
+                with_failure: run_args.with_failure,
+                metrics_file: run_args.metrics_file,
+            }, &state);
+            drop(lock);
+            or_exit(result);
+            if let Some(junit) = run_args.junit {
+                write_junit(&project, &path, false, &junit);
+            }
+            if state.is_aborted() {
+                std::process::exit(2);
+            }
         }
         Action::Clean(clean_args) => {
-            if Path::new(&project.summary_file).exists() {
-                let valid_answers = ["", "y", "Y", "n", "N"];
-                let mut answer = String::new();
-                loop {
-                    eprint!("The project has been executed. Would you save the previous results before cleaning the project ? [Y/n] ");
-                    stdout().flush().unwrap();
-                    stdin().read_line(&mut answer).expect("Cannot read stdin");
-                    let answer = answer.trim();
-                    if valid_answers.iter().any(|&it| it == answer) {
-                        break;
-                    }
-                }
-
-                let positive_answers = &valid_answers[0..3];
-                let answer = answer.trim();
-                if positive_answers.contains(&answer) {
-                    let zip_path = zip_path.replace(".zip", ".backup.zip");
-                    zip_project(&zip_path, &project, &clean_args.zip_with);
+            let _lock = or_exit(lock_project(&project, &clean_args.lock));
+            if !clean_args.no_backup && Path::new(&project.summary_file).exists() {
+                let interactive = !clean_args.yes
+                    && !clean_args.no_input
+                    && std::env::var_os("CI").is_none()
+                    && stdin().is_terminal();
+                if !interactive || ask_for_backup() {
+                    or_exit(zip_project(&backup_file(&path, &project), &project, &clean_args.zip));
+                    archived = true;
                 }
             }
-            project.clean();
+            // A selective clean keeps the results of the other computations, in the working directory
+            if clean_args.only.is_some() || clean_args.with_failure || clean_args.with_timeout {
+                project.clean_selected(&clean_args.only, clean_args.with_failure, clean_args.with_timeout);
+            } else {
+                project.clean();
+                archived = true;
+            }
         }
         Action::Show(show_args) => {
             match show_args.action {
-                ShowAction::Notes => print_notes(&project),
-                ShowAction::Summary(Summary { sort }) => {
+                ShowAction::Notes(Notes { plain, theme }) => {
+                    let theme = theme.unwrap_or(project.notes_theme);
+                    // An archive may embed longer notes in a NOTES.md entry at its root, e.g. added with `zip archive.zip NOTES.md`
+                    let notes = if is_zip_archive {
+                        read_zip_entry(&path, "NOTES.md")
+                    } else {
+                        None
+                    };
+                    print_notes(notes.as_ref().or(project.description.as_ref()), plain || !stdout().is_terminal(), theme)
+                }
+                ShowAction::Summary(Summary { sort, filter, columns, stats, head, tail, assume_width, sample_widths, watch, junit }) => {
+                    if let Some(junit) = junit {
+                        write_junit(&project, &path, is_zip_archive, &junit);
+                    }
                     eprintln!("{}", &project.summary_file);
-                    let sort_columns = sort;
-                    let result = if is_zip_archive {
+                    let color = !no_color
+                        && std::env::var_os("NO_COLOR").is_none_or(|it| it.is_empty())
+                        && std::io::stderr().is_terminal();
+                    if color {
+                        // colored only looks at stdout, the summary is printed on stderr
+                        colored::control::set_override(true);
+                    }
+                    let widths = match (assume_width, sample_widths) {
+                        (Some(width), _) => ColumnWidths::Assumed(width),
+                        (None, Some(sample)) => ColumnWidths::Sampled(sample),
+                        (None, None) => ColumnWidths::Measured,
+                    };
+                    let options = SummaryOptions { sort_columns: sort, filters: filter, columns, stats, head, tail, color, widths };
+                    let result = if let Some(interval) = watch {
+                        if is_zip_archive {
+                            error!("The summary of an archive does not change, it cannot be watched");
+                            std::process::exit(1);
+                        }
+                        let interval = interval.map(Into::into).unwrap_or(Duration::from_secs(2));
+                        watch_summary(Path::new(&project.summary_file), &options, interval)
+                    } else if is_zip_archive {
                         /*let mut archive = zip::ZipArchive::new(String::new()).unwrap();
                         let summary_file = archive.by_name(&project.summary_file).unwrap();
                         let mut reader = BufReader::new(summary_file);
-                        print_summary(&mut reader, sort_columns)*/
+                        print_summary(&mut reader, &options)*/
                         Ok(())
                     } else {
-                        if let Ok(summary_file) = File::open(&project.summary_file) {
-                            let mut reader = BufReader::new(summary_file);
-                            print_summary(&mut reader, sort_columns)
-                        } else {
-                            Ok(())
+                        project.check_fingerprint(false);
+                        match print_summary_file(Path::new(&project.summary_file), &options) {
+                            Err(e) if e.kind() == ErrorKind::NotFound => {
+                                warn!("There is no summary yet, the project has not run");
+                                Ok(())
+                            }
+                            result => result,
                         }
                     };
-                    result.expect("Cannot read the summary file");
+                    if let Err(e) = result {
+                        error!("Cannot display the summary file: {}", e);
+                        std::process::exit(1);
+                    }
                 }
                 ShowAction::Status(Status { only }) => {
                     project.display_status(&only);
                 }
+                ShowAction::List(Status { only }) => {
+                    project.display_list(&only);
+                }
+                ShowAction::Command(ShowCommand { name }) => {
+                    if !project.display_command(&name) {
+                        error!("There is no computation named {}", name);
+                        std::process::exit(1);
+                    }
+                }
+                ShowAction::Version => {
+                    println!("configuration: {}", previous_version);
+                    if is_zip_archive {
+                        let producer = project.whitesmith_version.as_deref().unwrap_or("unknown");
+                        println!("archived by whitesmith: {}", producer);
+                    }
+                    println!("whitesmith: {}", env!("CARGO_PKG_VERSION"));
+                }
                 ShowAction::Json(Json { pretty }) => {
                     if pretty {
                         println!("{}", serde_json::ser::to_string_pretty(&project).unwrap());
@@ -315,188 +668,171 @@ fn main() {
             }
         }
         Action::Zip(zip) => {
-            zip_project(&zip_path, &project, &zip.zip_with);
-        }
-    }
-}
-
-fn print_summary<RS>(reader: &mut BufReader<RS>, sort_columns: Option<Vec<String>>) -> std::io::Result<()>
-    where RS: std::io::Read {
-    let mut col_sizes = Vec::new();
-    let mut lines = Vec::new();
-
-    let mut headers = None;
-
-    for line in reader.lines() {
-        let line = line?;
-        let parts = line.split('\t')
-            .map(String::from)
-            .collect::<Vec<_>>();
-        if let None = headers {
-            headers = Some(parts.clone());
+            if let Some(archive) = &zip.append {
+                or_exit(append_to_archive(archive, &project, &zip.options));
+            } else {
+                or_exit(zip_project(Path::new(&zip_path), &project, &zip.options));
+                archived = true;
+            }
         }
-        let parts_len = parts.iter()
-            .map(&String::len)
-            .collect::<Vec<_>>();
-        let mut i = 0;
-        while i < usize::min(col_sizes.len(), parts.len()) {
-            col_sizes[i] = usize::max(col_sizes[i], parts_len[i]);
-            i += 1;
+        Action::Validate(validate_args) => {
+            apply_overrides(&mut project, &validate_args.overrides);
+            let problems = project.validate();
+            if problems.is_empty() {
+                eprintln!("The configuration is valid");
+            } else {
+                eprintln!("The configuration is invalid:");
+                for problem in &problems {
+                    eprintln!(" - {}", problem);
+                }
+                std::process::exit(1);
+            }
         }
-
-        while col_sizes.len() < parts.len() {
-            col_sizes.push(parts_len[i]);
-            i += 1;
+        Action::Migrate => unreachable!(),
+        Action::Extract(Extract { directory }) => {
+            if !is_zip_archive {
+                error!("Only a zip archive can be extracted");
+                std::process::exit(1);
+            }
+            or_exit(extract_project(&path, &project, &directory));
         }
-        lines.push(parts);
-    }
-
-    if let Some(header) = headers {
-        if let Some(sort_columns) = sort_columns {
-            let empty_string = String::new();
-            lines[1..].sort_by(|lhs, rhs| {
-                for column in &sort_columns {
-                    let (column, rev) = if column.starts_with('~') {
-                        (column.chars().skip(1).collect::<String>(), true)
-                    } else {
-                        (column.to_string(), false)
-                    };
-
-                    if let Some(index) = header.iter().position(|it| it.eq_ignore_ascii_case(&column)) {
-                        let mut comparison = human_sort::compare(
-                            &lhs.get(index).unwrap_or(&empty_string),
-                            &rhs.get(index).unwrap_or(&empty_string),
-                        );
-
-                        if rev { comparison = comparison.reverse(); }
-
-                        if comparison != Ordering::Equal {
-                            return comparison;
-                        }
+        Action::Merge(Merge { archives, output, with_logs, on_conflict }) => {
+            let archives = std::iter::once(path.clone())
+                .chain(archives)
+                .map(|path| {
+                    if path.extension() != Some(OsStr::new("zip")) {
+                        error!("Only zip archives can be merged, {} is not one", path.display());
+                        std::process::exit(1);
                     }
+                    let summary = summary_file(&path, &project.versioning, &Layout::default(), true);
+                    Archive { path, summary }
+                })
+                .collect::<Vec<_>>();
+            let output = output.unwrap_or_else(|| {
+                let name = archives[0].summary.trim_end_matches(".csv");
+                path.with_file_name(format!("{}.merged.zip", name))
+            });
+            let output_summary = summary_file(&output, &project.versioning, &Layout::default(), true);
+            match merge(&archives, &output, &output_summary, with_logs, on_conflict) {
+                Ok(nb_rows) => info!("{} rows merged in {}", nb_rows, output.display()),
+                Err(e) => {
+                    error!("{}", e);
+                    std::process::exit(1);
                 }
-                Ordering::Equal
+            }
+        }
+        Action::Compare(Compare { other, threshold }) => {
+            let baseline = read_summary(&path, &project.summary_file, is_zip_archive).unwrap_or_else(|| {
+                error!("Cannot read the summary of {}", path.display());
+                std::process::exit(1);
+            });
+            let other_summary = read_other_summary(&other).unwrap_or_else(|| {
+                error!("Cannot read the summary of {}", other.display());
+                std::process::exit(1);
             });
+            match print_comparison(&baseline, &other_summary, threshold) {
+                Ok(false) => {}
+                Ok(true) => std::process::exit(1),
+                Err(e) => {
+                    error!("Cannot compare the summaries: {}", e);
+                    std::process::exit(1);
+                }
+            }
         }
     }
+    match temporary_directory {
+        Some(directory) if archived => directory.remove(),
+        Some(directory) => info!("The results are in {}, it is deleted once they are archived by `zip` or `clean`", directory.path.display()),
+        None => {}
+    }
+    debug!("Done in {:?}", start.elapsed());
+}
 
-    for line in lines {
-        for (i, part) in line.iter().enumerate() {
-            eprint!("{:1$}", part, col_sizes[i] + 3);
+/// Ctrl-C aborts the run: the computations in progress finish, a second Ctrl-C kills them. Installed once per process
+fn handle_signals(state: &Arc<RunState>, pause_children: bool) {
+    let interrupted = state.clone();
+    ctrlc::set_handler(move || {
+        if interrupted.abort() {
+            interrupted.kill_children();
+        } else {
+            warn!("Interrupted, waiting for the computations in progress (press Ctrl-C again to kill them)");
         }
-        eprintln!();
-    }
+    }).expect("Cannot init CTRL-C handler");
 
-    Ok(())
+    #[cfg(unix)]
+    handle_pause_signals(state.clone(), pause_children);
+    #[cfg(not(unix))]
+    let _ = pause_children;
 }
 
-fn zip_project(zip_path: &str, project: &Project, files_to_add: &Vec<PathBuf>) {
-    let zip_file = File::create(zip_path)
-        .expect("Cannot create the zip archive");
-    let mut archive = RecursiveZipWriter::new(zip_file)
-        .compression_method(CompressionMethod::Stored);
-
-    let mut paths = HashSet::new();
-
-    archive.add_path(Path::new(&project.log_directory))
-        .expect("Fail to add the log directory to the zip archive");
-    paths.insert(PathBuf::from(&project.log_directory));
-
-    archive.add_path(Path::new(&project.summary_file))
-        .expect("Fail to add the summary file to the zip archive");
-    paths.insert(PathBuf::from(&project.summary_file));
-
-    archive.add_path(Path::new(&project.working_directory).join("last_running_configuration.ron").as_path())
-        .expect("Cannot add the running configuration file to the zip archive");
-    paths.insert(PathBuf::from(&project.working_directory).join("last_running_configuration.ron"));
-
-    let serialized_project = ron::ser::to_string_pretty(project, PrettyConfig::default())
-        .expect("Cannot serialize the project file to toml");
-    archive.add_buf(serialized_project.as_bytes(), Path::new("configuration.ron"))
-        .expect("Fail to add the configuration file to the zip archive");
-    paths.insert(PathBuf::from("configuration.ron"));
-
-    for file_to_add in &project.zip_with {
-        let full_path = restore_path(&PathBuf::from(&file_to_add), &project.aliases);
-        if !paths.contains(&full_path) {
-            archive.add_path(&full_path)
-                .expect(&format!("Fail to add {} to the zip archive", file_to_add));
-            paths.insert(full_path);
+/// SIGTSTP pauses the run: the computations in progress go on (or are stopped with SIGSTOP when
+/// `stop_children` is set) but no new one starts. SIGCONT resumes it.
+/// e.g. `kill -TSTP <pid>` then `kill -CONT <pid>`
+#[cfg(unix)]
+fn handle_pause_signals(state: Arc<RunState>, stop_children: bool) {
+    use signal_hook::consts::{SIGCONT, SIGTSTP};
+    // Handled rather than blocked: a blocked signal would stay blocked in the computations, which inherit the mask
+    let mut signals = signal_hook::iterator::Signals::new([SIGTSTP, SIGCONT])
+        .expect("Cannot handle SIGTSTP and SIGCONT");
+
+    std::thread::spawn(move || for signal in signals.forever() {
+        let pause = signal == SIGTSTP;
+        state.set_paused(pause);
+        if pause {
+            info!("Paused, no new computation will start until SIGCONT");
+        } else {
+            info!("Resumed");
         }
-    }
-    for file_to_add in files_to_add.iter() {
-        let full_path = restore_path(file_to_add, &project.aliases);
-        if !paths.contains(&full_path) {
-            archive.add_path(&full_path)
-                .expect(&format!("Fail to add {:?} to the zip archive", file_to_add));
-            paths.insert(full_path);
+        if stop_children {
+            for child in state.children.snapshot() {
+                send_signal(child, if pause { libc::SIGSTOP } else { libc::SIGCONT });
+            }
         }
-    }
-
+    });
+}
 
-    let archive = archive.finish()
-        .expect("Fail to build the archive");
+/// The value of an action of the crate, or exits once its error is logged
+fn or_exit<T>(result: Result<T, String>) -> T {
+    result.unwrap_or_else(|e| {
+        for line in e.lines() {
+            error!("{}", line);
+        }
+        std::process::exit(1);
+    })
+}
 
-    eprintln!("{:?}", archive);
+/// Asks whether the previous results must be saved, an empty answer or the end of the input means yes.
+fn ask_for_backup() -> bool {
+    let mut answer = String::new();
+    loop {
+        eprint!("The project has been executed. Would you save the previous results before cleaning the project ? [Y/n] ");
+        stdout().flush().unwrap();
+        answer.clear();
+        if stdin().read_line(&mut answer).expect("Cannot read stdin") == 0 {
+            return true;
+        }
+        match answer.trim() {
+            "" | "y" | "Y" => return true,
+            "n" | "N" => return false,
+            _ => {}
+        }
+    }
 }
 
-fn print_notes(project: &Project) {
-    if let Some(description) = &project.description {
+fn print_notes(notes: Option<&String>, plain: bool, theme: Theme) {
+    if let Some(description) = notes {
+        if plain {
+            println!("{}", description.trim());
+            return;
+        }
+
         let mut description = description.trim().to_owned();
 
         description.insert_str(0, "\n---\n");
         description.push_str("\n---\n");
 
-        let mut skin = MadSkin::default_dark();
-        skin.bold.set_fg(Color::Red);
-        skin.print_text(&description);
-
-        // eprintln!("{}", &description);
+        theme.skin().print_text(&description);
     } else {
         eprintln!("The configuration doesn't contain notes.")
     }
 }
-
-fn run_project(
-    project: Arc<Project>,
-    nb_threads: Option<usize>,
-    with_in_progress: bool,
-    with_timeout: bool,
-    with_failure: bool,
-) {
-    if project.requires_overrides() {
-        return;
-    }
-
-    if with_in_progress {
-        project.unlock_in_progress();
-    }
-
-    if with_timeout {
-        project.unlock_timeout();
-    }
-
-    if with_failure {
-        project.unlock_failed();
-    }
-
-    ctrlc::set_handler(|| {
-        { *ABORT.lock().unwrap() = true; }
-        let children = CHILDREN.lock().unwrap();
-        for &child in children.iter() {
-            eprintln!("Send Kill to {}", child);
-            kill(child);
-        }
-        std::process::exit(2);
-    }).expect("Cannot init CTRL-C handler");
-
-    if let Some(limits) = &project.limits {
-        if let Err(e) = limits.apply() {
-            eprintln!("Cannot apply project limitations\n{:?}", e);
-        }
-    }
-
-    let pool = ThreadPool::new(nb_threads.unwrap_or(1));
-    project.run(pool.clone());
-    pool.join();
-}
\ No newline at end of file
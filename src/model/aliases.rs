@@ -1,8 +1,9 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 
-pub type Aliases = HashMap<String, Alias>;
+pub type Aliases = BTreeMap<String, Alias>;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -13,15 +14,15 @@ pub enum Alias {
     String(String)
 }
 
-impl ToString for Alias {
-    fn to_string(&self) -> String {
-        let inner_type: &dyn ToString = match self {
+impl Display for Alias {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let inner_type: &dyn Display = match self {
             Alias::Boolean(b) => b,
             Alias::Integer(i) => i,
-            Alias::Float(f) => f,
+            Alias::Float(fl) => fl,
             Alias::String(s) => s,
         };
-        inner_type.to_string()
+        inner_type.fmt(f)
     }
 }
 
@@ -43,4 +44,39 @@ impl FromStr for Alias {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// `KEY:VALUE` assignment of an alias. Only the first separator splits the key from the value, so that
+/// `URL:https://host:8080` works. A value between quotes is kept as a string.
+#[derive(Clone, Debug)]
+pub struct Override {
+    pub key: String,
+    pub value: Alias,
+}
+
+impl FromStr for Override {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Override::parse(s, ':')
+    }
+}
+
+impl Override {
+    pub fn parse(s: &str, separator: char) -> Result<Self, String> {
+        let (key, value) = s.split_once(separator)
+            .ok_or_else(|| format!("Cannot parse '{}' as an override, expected KEY{}VALUE", s, separator))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("Cannot parse '{}' as an override, the key is empty", s));
+        }
+        let value = value.trim();
+        let quoted = ['"', '\''].iter()
+            .find_map(|&quote| value.strip_prefix(quote).and_then(|it| it.strip_suffix(quote)));
+        let value = match quoted {
+            Some(value) => Alias::String(value.to_owned()),
+            None => value.parse().unwrap(),
+        };
+        Ok(Override { key: key.to_owned(), value })
+    }
+}
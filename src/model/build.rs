@@ -0,0 +1,42 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use ron::ser::PrettyConfig;
+use serde::{Serialize, Deserialize};
+use log::warn;
+use crate::model::versioning::checksum;
+
+/// Outcome of the last successful build, kept in the working directory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// Checksum of the build command and of the source tree once built
+    pub fingerprint: String,
+    #[serde(with = "humantime_serde")]
+    pub duration: Duration,
+    pub finished_at: String,
+}
+
+impl BuildInfo {
+    pub fn load(path: &Path) -> Option<BuildInfo> {
+        fs::read_to_string(path).ok()
+            .and_then(|content| ron::de::from_str(&content).ok())
+    }
+
+    pub fn save(&self, path: &Path) {
+        let content = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .expect("Cannot serialize the build information");
+        if let Err(e) = fs::write(path, content) {
+            warn!("Cannot save the build information in {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// The tree is hashed after the build, so that the files produced by the build are part of the fingerprint
+/// and removing or modifying them triggers a new build.
+pub fn fingerprint(build_command: &str, source_directory: &Path) -> Option<String> {
+    let sources = checksum(source_directory).ok()?;
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(build_command.as_bytes());
+    hasher.update(sources.as_bytes());
+    Some(format!("{:08x}", hasher.finalize()))
+}
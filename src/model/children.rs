@@ -0,0 +1,41 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, PoisonError};
+
+const SHARDS: usize = 16;
+
+/// Pids of the computations in progress, killed on abort. The set is sharded by pid so that the threads starting
+/// and reaping hundreds of children do not contend for a single lock, and each lock is only held to update a shard.
+pub struct Children {
+    shards: [Mutex<HashSet<u32>>; SHARDS],
+}
+
+impl Children {
+    pub fn new() -> Self {
+        Children { shards: Default::default() }
+    }
+
+    fn shard(&self, pid: u32) -> &Mutex<HashSet<u32>> {
+        &self.shards[pid as usize % SHARDS]
+    }
+
+    pub fn insert(&self, pid: u32) {
+        self.shard(pid).lock().unwrap_or_else(PoisonError::into_inner).insert(pid);
+    }
+
+    pub fn remove(&self, pid: u32) {
+        self.shard(pid).lock().unwrap_or_else(PoisonError::into_inner).remove(&pid);
+    }
+
+    /// The pids at the time of the call, to signal them without holding any lock
+    pub fn snapshot(&self) -> Vec<u32> {
+        self.shards.iter()
+            .flat_map(|shard| shard.lock().unwrap_or_else(PoisonError::into_inner).iter().copied().collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+impl Default for Children {
+    fn default() -> Self {
+        Children::new()
+    }
+}
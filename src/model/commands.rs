@@ -1,19 +1,45 @@
-use std::process::{Command, Stdio};
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::fs::File;
+use std::io;
+use std::io::{IsTerminal, Read};
+use std::thread;
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
-use crate::model::computation_result::ComputationResult;
-use wait_timeout::ChildExt;
+use crate::model::computation_result::{ComputationResult, CpuTime};
 use serde::{Serialize, Deserialize};
 use std::fmt::{Debug, Formatter};
-use std::path::PathBuf;
-use crate::CHILDREN;
+use std::path::{Path, PathBuf};
+use crate::NO_WRAP;
 use crate::model::aliases::Aliases;
+use crate::model::children::Children;
+use crate::model::job::cmd_env::CmdEnv;
+use crate::model::limits::Limits;
+use log::{debug, error, info, log_enabled, warn, Level};
+use crate::model::job::capped_log::{pump, CappedLog, LogSink, Tee, HARD_LIMIT_FACTOR};
+use crate::model::job::checkpoints::Checkpoints;
+use std::sync::Arc;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Commands {
-    pub build: String,
+    pub build: BuildSteps,
     #[serde(default)]
     pub clean: String,
+    /// Directory the build and clean commands run in, relative to the source directory. Aliases are allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Bash script run before each computation, in its directory and with its aliases.
+    /// When it fails the computation is skipped and recorded as `BeforeError`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// Bash script run after each computation, even when it failed or timed out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// Bash script run once before the first computation, e.g. to start a database. The run is aborted when it fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setup: Option<String>,
+    /// Bash script run once after the last computation, even when the run is interrupted with Ctrl-C
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teardown: Option<String>,
 }
 
 #[cfg(target_os = "windows")]
@@ -25,22 +51,54 @@ pub fn kill(pid: u32) {
         .wait();
 }
 
-#[cfg(target_os = "linux")]
+/// Interrupts the whole process group of a computation, i.e. bash and the commands it started
+#[cfg(unix)]
 pub fn kill(pid: u32) {
-    let _ = Command::new("kill")
-        .args(&["-2", &pid.to_string()])
-        .spawn()
-        .unwrap()
-        .wait();
+    send_group_signal(pid, libc::SIGINT);
+}
+
+#[cfg(unix)]
+pub fn send_signal(pid: u32, signal: libc::c_int) {
+    unsafe { libc::kill(pid as libc::pid_t, signal); }
+}
+
+/// Sends `signal` to the process group led by `pid`, each computation being in its own
+#[cfg(unix)]
+pub fn send_group_signal(pid: u32, signal: libc::c_int) {
+    unsafe { libc::kill(-(pid as libc::pid_t), signal); }
+}
+
+/// Either a single build command or a list of commands run in order, e.g. `["./configure", "make", "make install"]`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum BuildSteps {
+    Single(String),
+    Sequence(Vec<String>),
+}
+
+impl BuildSteps {
+    pub fn steps(&self) -> Vec<&String> {
+        match self {
+            BuildSteps::Single(step) => vec![step],
+            BuildSteps::Sequence(steps) => steps.iter().collect(),
+        }
+    }
 }
 
 impl Commands {
-    fn generate_build(&self, shortcuts: &Aliases) -> BuildCommand {
-        BuildCommand { sub_command: generate_command(&self.build, shortcuts) }
+    fn generate_build(&self, shortcuts: &Aliases) -> Vec<BuildCommand> {
+        self.build.steps().into_iter()
+            .map(|step| BuildCommand { sub_command: generate_command(step, shortcuts) })
+            .collect()
     }
 
-    fn generate_executable(&self, shortcuts: &Aliases, cmd: &String) -> ExecutableCommand {
-        ExecutableCommand { bash_command: restore_str(cmd, shortcuts) }
+    fn generate_executable(&self, shortcuts: &Aliases, cmd: &str) -> ExecutableCommand {
+        ExecutableCommand { bash_command: restore_str(cmd, shortcuts), prefix: String::new(), env: Vec::new() }
+    }
+
+    /// Bash script run for `cmd`, once the aliases are restored
+    pub fn executable_script(&self, shortcuts: &Aliases, cmd: &str) -> String {
+        self.generate_executable(shortcuts, cmd).bash_command
     }
 
     fn generate_clean(&self, shortcuts: &Aliases) -> Option<BuildCommand> {
@@ -51,38 +109,97 @@ impl Commands {
         }
     }
 
+    /// Build commands once the aliases are restored, one per line
+    pub fn build_command_line(&self, shortcuts: &Aliases) -> String {
+        self.generate_build(shortcuts).iter()
+            .map(|it| format!("{:#?}", it.sub_command))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Runs the build steps in order, stopping at the first one that fails
     pub fn run_build(&self, working_directory: &str, shortcuts: &Aliases) {
-        let build_command = self.generate_build(shortcuts);
-        eprintln!("Building project: ");
-        eprintln!("$ {:?}", &build_command.sub_command);
-        if !build_command.run(working_directory) {
-            panic!("Cannot execute {:?}", build_command.sub_command);
-        }
-    }
-
-    pub fn run_exec(
-        &self,
-        working_directory: &str,
-        shortcuts: &Aliases,
-        cmd: &String,
-        err_file: File,
-        timeout: Option<Duration>,
-    ) -> ComputationResult {
-        let executable_command = self.generate_executable(shortcuts, cmd);
-        eprintln!("$ {:?}", &executable_command.bash_command);
-
-        if let Some(timeout) = timeout {
-            executable_command.run_with_timeout(working_directory, err_file, timeout)
-        } else {
-            executable_command.run(working_directory, err_file)
+        let build_commands = self.generate_build(shortcuts);
+        let working_directory = resolve_cwd(working_directory, self.cwd.as_deref(), shortcuts);
+        info!("Building project: ");
+        for (i, build_command) in build_commands.iter().enumerate() {
+            if log_enabled!(Level::Debug) {
+                info!("$ {:#?}", &build_command.sub_command);
+            } else {
+                info!("$ {:?}", &build_command.sub_command);
+            }
+            if !build_command.run(&working_directory) {
+                panic!("The build step {}/{} failed: {:#?}", i + 1, build_commands.len(), build_command.sub_command);
+            }
+        }
+    }
+
+    /// Runs the `setup` or `teardown` script in the directory of the build, its output goes to `log_file`
+    pub fn run_phase(&self, phase: &str, script: &str, working_directory: &str, shortcuts: &Aliases, log_file: &Path) -> bool {
+        let script = restore_str(script, shortcuts);
+        info!("{} $ {:?}", phase, script);
+        if let Some(directory) = log_file.parent() {
+            let _ = std::fs::create_dir_all(directory);
+        }
+        let log = match File::create(log_file).and_then(|file| Ok((file.try_clone()?, file))) {
+            Ok(log) => log,
+            Err(e) => {
+                error!("Cannot create the {} log {}: {}", phase, log_file.display(), e);
+                return false;
+            }
+        };
+        let status = Command::new("bash")
+            .current_dir(resolve_cwd(working_directory, self.cwd.as_deref(), shortcuts))
+            .args(["-c", &script])
+            .stdout(Stdio::from(log.0))
+            .stderr(Stdio::from(log.1))
+            .status();
+        match status {
+            Ok(status) if status.success() => true,
+            Ok(status) => {
+                error!("The {} failed ({}), see {}", phase, status, log_file.display());
+                false
+            }
+            Err(e) => {
+                error!("Cannot run the {}: {}", phase, e);
+                false
+            }
         }
     }
 
+    /// Runs the command of a computation, registered in `children` while it is in progress
+    pub fn run_exec(&self, cmd_env: &CmdEnv, working_directory: &str, logs: Logs, children: &Children) -> ComputationResult {
+        let project = &cmd_env.project;
+        let shortcuts = &cmd_env.aliases;
+        let cmd = &cmd_env.cmd;
+        let prefix = &cmd_env.prefix();
+        let mut executable_command = self.generate_executable(shortcuts, &cmd.cmd);
+        executable_command.prefix = prefix.to_owned();
+        executable_command.env = cmd.env.iter()
+            .map(|(name, value)| (name.clone(), restore_str(value, shortcuts)))
+            .collect();
+        info!("{}$ {:?}", prefix, &executable_command.bash_command);
+        if log_enabled!(Level::Debug) {
+            debug!("{}working directory: {}", prefix, working_directory);
+            // Only the variables set for the computation: the inherited ones may hold secrets that end up in archived logs
+            for (key, value) in &executable_command.env {
+                debug!("{}env {}={}", prefix, key, value);
+            }
+        }
+
+        let command = executable_command.command(working_directory, &logs, project.limits.as_ref(), project.strict_limits);
+        executable_command.run(command, logs, project.global_timeout, children)
+    }
+
     pub fn run_clean(&self, working_directory: &str, shortcuts: &Aliases) {
         if let Some(clean_command) = self.generate_clean(shortcuts) {
-            eprintln!("Cleaning project: ");
-            eprintln!("$ {:?}", &clean_command.sub_command);
-            if !clean_command.run(working_directory) {
+            info!("Cleaning project: ");
+            if log_enabled!(Level::Debug) {
+                info!("$ {:#?}", &clean_command.sub_command);
+            } else {
+                info!("$ {:?}", &clean_command.sub_command);
+            }
+            if !clean_command.run(&resolve_cwd(working_directory, self.cwd.as_deref(), shortcuts)) {
                 panic!("Cannot execute {:?}", clean_command.sub_command);
             }
         }
@@ -94,8 +211,23 @@ struct SubCommand {
     args: Vec<String>,
 }
 
+/// Width at which the command lines are wrapped: the width of the terminal, or none when stderr is not a terminal
+/// (so that the line can be copied and run as is) or with `--no-wrap`
+fn wrap_width() -> Option<usize> {
+    if *NO_WRAP.lock().unwrap() || !std::io::stderr().is_terminal() {
+        return None;
+    }
+    Some(crossterm::terminal::size().map(|(width, _)| width as usize).unwrap_or(80))
+}
+
+/// Long command lines are wrapped at the width of the terminal, unless formatted with `{:#?}`
 impl Debug for SubCommand {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = match wrap_width() {
+            Some(width) if !f.alternate() => width,
+            _ => return write!(f, "{} {}", self.executable, self.args.join(" ")),
+        };
+
         let mut content = String::from(&self.executable);
         content.push(' ');
         let mut curr_len = self.executable.len() + 1;
@@ -103,7 +235,7 @@ impl Debug for SubCommand {
         for (i, element) in self.args.iter().enumerate() {
             content.push_str(element);
             curr_len += element.len();
-            if i + 1 < self.args.len() && curr_len + self.args[i + 1].len() > 80 {
+            if i + 1 < self.args.len() && curr_len + self.args[i + 1].len() > width {
                 content.push_str(" \\\n   > ");
                 curr_len = 0;
             } else if i + 1 < self.args.len() {
@@ -131,68 +263,250 @@ impl BuildCommand {
     }
 }
 
+/// Log files of a computation. Without size limit nor tee the output of the process goes straight to the files,
+/// otherwise it goes through pipes and is copied by a thread per file.
+pub struct Logs {
+    pub err: (PathBuf, File),
+    pub out: (PathBuf, File),
+    pub max_bytes: Option<u64>,
+    /// Prefix of the lines echoed to the terminal
+    pub tee: Option<String>,
+    pub checkpoints: Option<Arc<Checkpoints>>,
+}
+
+impl Logs {
+    fn is_piped(&self) -> bool {
+        self.max_bytes.is_some() || self.tee.is_some() || self.checkpoints.is_some()
+    }
+
+    fn redirect(&self, command: &mut Command) {
+        if self.is_piped() {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        } else {
+            command.stdout(Stdio::from(self.out.1.try_clone().expect("Cannot duplicate the stdout file")))
+                .stderr(Stdio::from(self.err.1.try_clone().expect("Cannot duplicate the stderr file")));
+        }
+    }
+
+    fn capture(self, child: &mut Child) -> Vec<JoinHandle<()>> {
+        if !self.is_piped() {
+            return Vec::new();
+        }
+        let pid = child.id();
+        let prefix = self.tee;
+        let tee = |to_stderr| prefix.clone().map(|prefix| Tee { prefix, to_stderr });
+        let mut pumps = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            pumps.push(spawn_pump(stdout, self.out, self.max_bytes, tee(false), self.checkpoints.clone(), pid));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            pumps.push(spawn_pump(stderr, self.err, self.max_bytes, tee(true), self.checkpoints, pid));
+        }
+        pumps
+    }
+}
+
+fn spawn_pump<R: Read + Send + 'static>(
+    reader: R,
+    (path, file): (PathBuf, File),
+    max_bytes: Option<u64>,
+    tee: Option<Tee>,
+    checkpoints: Option<Arc<Checkpoints>>,
+    pid: u32,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let sink = match max_bytes {
+            Some(max_bytes) => LogSink::Capped(CappedLog::new(&path, file, max_bytes)),
+            None => LogSink::Plain(file),
+        };
+        let kill_process = || {
+            warn!("Killing {} that wrote more than {} bytes in {}", pid, max_bytes.unwrap_or_default() * HARD_LIMIT_FACTOR, path.display());
+            #[cfg(unix)]
+            send_group_signal(pid, libc::SIGKILL);
+        };
+        if let Err(e) = pump(reader, sink, tee, checkpoints.as_deref(), kill_process) {
+            error!("Cannot write the log {}: {}", path.display(), e);
+        }
+    })
+}
+
+/// How long the logs of a killed command are still copied, see `join_all`
+const KILLED_PUMP_GRACE: Duration = Duration::from_secs(5);
+
+/// Waits for the pumps to copy the logs. Once the command has been killed, a process it moved out of its process group
+/// may keep its outputs open: the pumps are then left behind after `KILLED_PUMP_GRACE`.
+fn join_all(pumps: Vec<JoinHandle<()>>, killed: bool, prefix: &str) {
+    let deadline = Instant::now() + KILLED_PUMP_GRACE;
+    for pump in pumps {
+        while killed && !pump.is_finished() {
+            if Instant::now() >= deadline {
+                warn!("{}The outputs of the killed command are still open, its logs may be incomplete", prefix);
+                return;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        let _ = pump.join();
+    }
+}
+
 struct ExecutableCommand {
     bash_command: String,
+    /// Prefix of the diagnostics printed for the command
+    prefix: String,
+    /// Variables added to the environment of the command
+    env: Vec<(String, String)>,
 }
 
 impl ExecutableCommand {
-    fn run(&self, working_directory: &str, err_file: File) -> ComputationResult {
+    fn command(&self, working_directory: &str, logs: &Logs, limits: Option<&Limits>, strict_limits: bool) -> Command {
+        let mut command = Command::new("bash");
+        command.current_dir(working_directory)
+            .args([ "-c", &self.bash_command ])
+            .envs(self.env.iter().map(|(name, value)| (name, value)));
+        logs.redirect(&mut command);
+
+        // In its own process group, a Ctrl-C in the terminal only reaches whitesmith which then decides
+        // whether the computation may finish. It cannot read the terminal from there.
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0)
+                .stdin(Stdio::null());
+        }
+
+        #[cfg(unix)]
+        if let Some(limits) = limits.cloned() {
+            use std::os::unix::process::CommandExt;
+            // Only the spawned process (and its own children) are constrained, not whitesmith
+            unsafe { command.pre_exec(move || limits.apply(strict_limits)); }
+        }
+        #[cfg(not(unix))]
+        let _ = (limits, strict_limits);
+
+        command
+    }
+
+    /// Durations since the command was started: until the process is spawned, until it exits and until its logs are written
+    fn debug_timings(&self, spawned: Duration, exited: Duration, logged: Duration) {
+        debug!("{}spawned in {:?}, exited after {:?}, logs written after {:?}", self.prefix, spawned, exited, logged);
+    }
+
+    /// Runs the command until it exits, or kills it once `timeout` is elapsed
+    fn run(&self, mut command: Command, logs: Logs, timeout: Option<Duration>, children: &Children) -> ComputationResult {
         let clock = Instant::now();
-        let mut child = Command::new("bash")
-            .current_dir(working_directory)
-            .args(&[ "-c", &self.bash_command ])
-            .stderr(Stdio::from(err_file))
+        let mut child = command
             .spawn()
-            .expect(&format!("The script cannot execute the following command:\n```\n$ {:?}\n```", self.bash_command));
+            .unwrap_or_else(|e| panic!("\nThe script cannot execute the following command:\n```\n$ {:?}\n```\n{}", self.bash_command, e));
+
+        let spawned = clock.elapsed();
 
         let pid = child.id();
-        { CHILDREN.lock().unwrap().insert(pid); }
-        let success = child.wait()
-            .map(|status| status.success());
-        { CHILDREN.lock().unwrap().remove(&pid); }
-
-        if let Ok(success) = success {
-            if success {
-                ComputationResult::Ok(clock.elapsed())
-            } else {
-                ComputationResult::Error(clock.elapsed())
+        children.insert(pid);
+        let pumps = logs.capture(&mut child);
+        let exit = wait(&mut child, timeout);
+        children.remove(pid);
+        let exited = clock.elapsed();
+        join_all(pumps, was_killed(&exit), &self.prefix);
+        self.debug_timings(spawned, exited, clock.elapsed());
+
+        match exit {
+            Ok((Some(status), cpu_time)) if status.success() => ComputationResult::Ok(clock.elapsed(), cpu_time),
+            Ok((Some(_), cpu_time)) => ComputationResult::Error(clock.elapsed(), cpu_time),
+            Ok((None, cpu_time)) => ComputationResult::Timeout(timeout.unwrap_or_default(), cpu_time),
+            Err(e) => panic!("\nThe script cannot execute the following command:\n```\n$ {:?}\n```\n{}", self.bash_command, e),
+        }
+    }
+}
+
+/// Whether the command timed out or was killed by a signal, e.g. for writing too much
+fn was_killed(exit: &io::Result<(Option<ExitStatus>, Option<CpuTime>)>) -> bool {
+    match exit {
+        Ok((None, _)) => true,
+        #[cfg(unix)]
+        Ok((Some(status), _)) => std::os::unix::process::ExitStatusExt::signal(status).is_some(),
+        _ => false,
+    }
+}
+
+/// Waits for `child` to exit and reaps it with `wait4` to read its CPU time. Once `timeout` is elapsed it is killed
+/// and its status is `None`.
+#[cfg(unix)]
+fn wait(child: &mut Child, timeout: Option<Duration>) -> io::Result<(Option<ExitStatus>, Option<CpuTime>)> {
+    use std::os::unix::process::ExitStatusExt;
+    use std::sync::{mpsc, Mutex};
+    use std::sync::mpsc::RecvTimeoutError;
+
+    let pid = child.id() as libc::pid_t;
+    let reaped = Arc::new(Mutex::new(false));
+    let (cancel, cancelled) = mpsc::channel::<()>();
+    let watchdog = timeout.map(|timeout| {
+        let reaped = reaped.clone();
+        thread::spawn(move || {
+            if cancelled.recv_timeout(timeout) != Err(RecvTimeoutError::Timeout) {
+                return false;
             }
-        } else {
-            panic!("\nThe script cannot execute the following command:\n```\n$ {:?}\n```", self.bash_command);
+            let reaped = reaped.lock().unwrap();
+            if !*reaped {
+                // Its whole process group, otherwise the commands it started keep running and its outputs open
+                send_group_signal(pid as u32, libc::SIGKILL);
+            }
+            !*reaped
+        })
+    });
+
+    // The process is left unreaped until the watchdog cannot kill it anymore, so that its pid cannot be reused meanwhile
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    while unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT) } != 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
         }
     }
+    *reaped.lock().unwrap() = true;
 
-    fn run_with_timeout(&self, working_directory: &str, err_file: File, timeout: Duration) -> ComputationResult {
-        let clock = Instant::now();
-        let mut child = Command::new("bash")
-            .current_dir(working_directory)
-            .args(&[ "-c", &self.bash_command ])
-            .stderr(Stdio::from(err_file))
-            .spawn()
-            .expect(&format!("\nThe script cannot execute the following command:\n```\n$ {:?}\n```", self.bash_command));
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    while unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } < 0 {
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+    drop(cancel);
+    let timed_out = watchdog.is_some_and(|it| it.join().unwrap_or(false));
 
-        let pid = child.id();
-        { CHILDREN.lock().unwrap().insert(pid); }
+    let duration = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    let cpu_time = CpuTime { user: duration(usage.ru_utime), system: duration(usage.ru_stime) };
+    let status = if timed_out { None } else { Some(ExitStatus::from_raw(status)) };
+    Ok((status, Some(cpu_time)))
+}
 
-        if let Ok(status) = child.wait_timeout(timeout) {
-            { CHILDREN.lock().unwrap().remove(&pid); }
-            return if let Some(success) = status.map(|s| s.success()) {
-                let _ = child.kill();
-                let _ = child.wait();
-                if success {
-                    ComputationResult::Ok(clock.elapsed())
-                } else {
-                    ComputationResult::Error(clock.elapsed())
-                }
-            } else {
+#[cfg(not(unix))]
+fn wait(child: &mut Child, timeout: Option<Duration>) -> io::Result<(Option<ExitStatus>, Option<CpuTime>)> {
+    use wait_timeout::ChildExt;
+
+    let status = match timeout {
+        None => Some(child.wait()?),
+        Some(timeout) => {
+            let status = child.wait_timeout(timeout)?;
+            if status.is_none() {
                 let _ = child.kill();
                 let _ = child.wait();
-                ComputationResult::Timeout(timeout)
-            };
-        } else {
-            { CHILDREN.lock().unwrap().remove(&pid); }
-            panic!();
+            }
+            status
         }
+    };
+    Ok((status, None))
+}
+
+/// `cwd` once the aliases are restored, relative to `source_directory` unless it is absolute
+pub fn resolve_cwd(source_directory: &str, cwd: Option<&str>, shortcuts: &Aliases) -> String {
+    match cwd {
+        Some(cwd) => Path::new(source_directory)
+            .join(restore_str(cwd, shortcuts))
+            .to_string_lossy()
+            .into_owned(),
+        None => source_directory.to_owned(),
     }
 }
 
@@ -211,7 +525,23 @@ pub fn restore_str(path: &str, shortcuts: &Aliases) -> String {
     path
 }
 
-pub fn restore_path(path: &PathBuf, shortcuts: &Aliases) -> PathBuf {
+/// Lists the `{ALIAS}` placeholders that are still present in `s` once the aliases have been restored.
+pub fn unresolved_aliases(s: &str) -> Vec<String> {
+    let mut unresolved = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        if let Some(end) = rest.find('}') {
+            let name = &rest[..end];
+            if !name.is_empty() && name.chars().all(|it| it.is_alphanumeric() || it == '_') {
+                unresolved.push(name.to_owned());
+            }
+        }
+    }
+    unresolved
+}
+
+pub fn restore_path(path: &Path, shortcuts: &Aliases) -> PathBuf {
     PathBuf::from(restore_str(path.to_str().unwrap(), shortcuts))
 }
 
@@ -1,42 +1,112 @@
 use std::time::{Duration};
-use std::fmt::{Formatter, Debug};
+use std::fmt::{Formatter, Debug, Display};
 use colored::Colorize;
 
+/// User and system CPU time of a computation, including the processes it waited for
+#[derive(Debug, Default, Copy, Clone)]
+pub struct CpuTime {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+/// Result of a computation with its wall-clock time, and its CPU time when the command ran (only on Unix)
 #[derive(Copy, Clone)]
-pub enum ComputationResult { Ok(Duration), Timeout(Duration), Error(Duration) }
+pub enum ComputationResult {
+    Ok(Duration, Option<CpuTime>),
+    Timeout(Duration, Option<CpuTime>),
+    Error(Duration, Option<CpuTime>),
+    /// The `before` hook failed, the computation did not run
+    BeforeError(Duration),
+    /// The `when` condition of the computation failed, it did not run
+    Skipped(Duration),
+    /// A `build` step of the computation failed, it did not run
+    BuildError(Duration),
+}
 
 impl ComputationResult {
     pub fn is_err(&self) -> bool {
+        matches!(self, ComputationResult::Error(..) | ComputationResult::BeforeError(_) | ComputationResult::BuildError(_))
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ComputationResult::Timeout(..))
+    }
+
+    pub fn is_skipped(&self) -> bool {
+        matches!(self, ComputationResult::Skipped(_))
+    }
+
+    pub fn duration(&self) -> Duration {
         match self {
-            ComputationResult::Error(_) => true,
-            _ => false
+            ComputationResult::Ok(duration, _)
+            | ComputationResult::Timeout(duration, _)
+            | ComputationResult::Error(duration, _)
+            | ComputationResult::BeforeError(duration)
+            | ComputationResult::Skipped(duration)
+            | ComputationResult::BuildError(duration) => *duration,
         }
     }
 
-    pub fn is_timeout(&self) -> bool {
+    pub fn cpu_time(&self) -> Option<CpuTime> {
         match self {
-            ComputationResult::Timeout(_) => true,
-            _ => false
+            ComputationResult::Ok(_, cpu_time)
+            | ComputationResult::Timeout(_, cpu_time)
+            | ComputationResult::Error(_, cpu_time) => *cpu_time,
+            _ => None,
         }
     }
 }
 
+/// Results of the computations of a run, in the order they ended. A computation has a result per iteration.
+#[derive(Debug, Default, Clone)]
+pub struct RunReport {
+    pub results: Vec<(String, ComputationResult)>,
+}
+
+impl RunReport {
+    /// Whether every computation succeeded or was skipped
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|(_, result)| !result.is_err() && !result.is_timeout())
+    }
+
+    /// Results of the computations that failed or timed out
+    pub fn failures(&self) -> impl Iterator<Item = &(String, ComputationResult)> {
+        self.results.iter().filter(|(_, result)| result.is_err() || result.is_timeout())
+    }
+}
+
+impl Display for CpuTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "CPU: {:.2}s user {:.2}s sys", self.user.as_secs_f64(), self.system.as_secs_f64())
+    }
+}
+
 impl Debug for ComputationResult {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            ComputationResult::Error(time) => f.write_fmt(format_args!("{}     Time:  {:.2}s ({})", "Error".red(), time.as_millis() as f64 / 1000.0, humantime::Duration::from(*time))),
-            ComputationResult::Ok(time) => f.write_fmt(format_args!("{}      Time:  {:.2}s ({})", "Done".green(), time.as_millis() as f64 / 1000.0, humantime::Duration::from(*time))),
-            ComputationResult::Timeout(limit) => f.write_fmt(format_args!("{}   Limit: {}", "Timeout".yellow(), humantime::Duration::from(*limit)))
+            ComputationResult::Error(time, _) => f.write_fmt(format_args!("{}     Time:  {:.2}s ({})", "Error".red(), time.as_millis() as f64 / 1000.0, humantime::Duration::from(*time))),
+            ComputationResult::Ok(time, _) => f.write_fmt(format_args!("{}      Time:  {:.2}s ({})", "Done".green(), time.as_millis() as f64 / 1000.0, humantime::Duration::from(*time))),
+            ComputationResult::Timeout(limit, _) => f.write_fmt(format_args!("{}   Limit: {}", "Timeout".yellow(), humantime::Duration::from(*limit))),
+            ComputationResult::BeforeError(_) => f.write_fmt(format_args!("{} The before hook failed", "BeforeError".red())),
+            ComputationResult::Skipped(_) => f.write_fmt(format_args!("{}   The when condition failed", "Skipped".cyan())),
+            ComputationResult::BuildError(_) => f.write_fmt(format_args!("{} The build failed", "BuildError".red())),
+        }?;
+        match self.cpu_time() {
+            Some(cpu_time) => write!(f, "  {}", cpu_time),
+            None => Ok(()),
         }
     }
 }
 
-impl ToString for ComputationResult {
-    fn to_string(&self) -> String {
+impl Display for ComputationResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            ComputationResult::Ok(_) => String::from("Ok"),
-            ComputationResult::Timeout(_) => String::from("Timeout"),
-            ComputationResult::Error(_) => String::from("Error"),
+            ComputationResult::Ok(..) => f.write_str("Ok"),
+            ComputationResult::Timeout(..) => f.write_str("Timeout"),
+            ComputationResult::Error(..) => f.write_str("Error"),
+            ComputationResult::BeforeError(_) => f.write_str("BeforeError"),
+            ComputationResult::Skipped(_) => f.write_str("Skipped"),
+            ComputationResult::BuildError(_) => f.write_str("BuildError"),
         }
     }
 }
@@ -0,0 +1,351 @@
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read};
+use std::path::{Path, PathBuf};
+use log::{error, warn};
+use ron::ser::PrettyConfig;
+use serde::de::DeserializeOwned;
+use crate::model::{log_directory, migration, source_directory, summary_file, working_directory};
+use crate::model::aliases::Aliases;
+use crate::model::job::Job;
+use crate::model::layout::Layout;
+use crate::model::project::{Include, Project, ProjectVersionOnly};
+use crate::model::version::Version;
+use crate::tools::archive::read_zip_entry;
+
+#[derive(Clone, Copy)]
+pub enum ConfigurationFormat {
+    Ron,
+    Toml,
+    Yaml,
+}
+
+impl ConfigurationFormat {
+    fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "ron" => Some(ConfigurationFormat::Ron),
+            "toml" => Some(ConfigurationFormat::Toml),
+            "yaml" | "yml" => Some(ConfigurationFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Aliases defined by whitesmith from the location of the project
+pub const RESERVED_ALIASES: [&str; 4] = ["PROJECT", "SOURCES", "LOGS", "SUMMARY_FILE"];
+
+const CONFIGURATION_NAMES: [&str; 2] = ["configuration", "whitesmith"];
+const CONFIGURATION_EXTENSIONS: [&str; 4] = ["ron", "toml", "yaml", "yml"];
+
+pub fn is_zip_archive(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("zip"))
+}
+
+/// Loads the project of a configuration file, a results archive or a directory containing a configuration,
+/// the way the command line does before running an action. An older configuration is migrated in memory.
+pub fn open_project(path: &Path) -> Project {
+    let path = locate_configuration(path);
+    let (configuration, format) = read_configuration(&path, is_zip_archive(&path));
+
+    let (mut project, migrated) = parse_project(&path, &configuration, format);
+    if let Some(previous_version) = migrated {
+        warn!("The configuration uses version {} and has been migrated to {} in memory", previous_version, project.version);
+    }
+    resolve_project(&mut project, &path);
+    place_project(&mut project, &path);
+    project
+}
+
+/// Parses the project of a configuration. The document of an older configuration is migrated to the current shape
+/// before it is deserialized, the project then has the current version. Returns the version it was migrated from.
+/// A version outside of `migration::supported_range` is refused.
+pub fn parse_project(path: &Path, configuration: &str, format: ConfigurationFormat) -> (Project, Option<Version>) {
+    let version = parse_configuration::<ProjectVersionOnly>(path, configuration, format).version;
+    if !migration::is_supported(&version) {
+        panic!("{} is not accepted by the current whitesmith instance. Supported versions are {}", version, migration::supported_range());
+    }
+
+    let mut project = if migration::changes_shape(&version) {
+        let mut document = parse_document(path, configuration, format);
+        migration::migrate(&mut document, &version);
+        serde_json::from_value::<Project>(document).unwrap_or_else(|e| {
+            error!("Cannot parse the configuration file {} migrated from {}: {}", path.display(), version, e);
+            std::process::exit(1);
+        })
+    } else {
+        parse_configuration::<Project>(path, configuration, format)
+    };
+    if version >= migration::CURRENT_VERSION {
+        return (project, None);
+    }
+    project.version = migration::CURRENT_VERSION;
+    (project, Some(version))
+}
+
+/// The configuration as a document, without the types of the project
+fn parse_document(path: &Path, configuration: &str, format: ConfigurationFormat) -> serde_json::Value {
+    match format {
+        // The fields of a ron struct can only be read as identifiers, which a json document does not accept
+        ConfigurationFormat::Ron => parse_configuration::<ron::Value>(path, configuration, format)
+            .into_rust()
+            .unwrap_or_else(|e| {
+                error!("Cannot parse the configuration file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }),
+        _ => parse_configuration(path, configuration, format),
+    }
+}
+
+/// The configuration file of `path`, looked for in it when it is a directory
+pub fn locate_configuration(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        find_configuration(path)
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Merges the included files into the project and resolves its layout
+pub fn resolve_project(project: &mut Project, path: &Path) {
+    let includes = std::mem::take(&mut project.include);
+    let mut visited = vec![path.canonicalize().unwrap_or_else(|_| path.to_owned())];
+    let included = resolve_includes(path, &includes, &mut visited);
+    merge_include(&mut project.aliases, &mut project.experiments, included);
+
+    // The files of an archive are in the archive, wherever they were when it was created
+    project.layout = if is_zip_archive(path) {
+        Layout::default()
+    } else {
+        project.layout.resolve(path, &project.aliases)
+    };
+}
+
+/// Sets the directories of the project from its layout, defines the reserved aliases and creates the directories
+pub fn place_project(project: &mut Project, path: &Path) {
+    let is_zip_archive = is_zip_archive(path);
+    project.working_directory = working_directory(path, &project.versioning, &project.layout);
+    project.source_directory = source_directory(path, &project.versioning, &project.layout);
+    project.log_directory = log_directory(path, &project.versioning, &project.layout);
+    project.summary_file = summary_file(path, &project.versioning, &project.layout, is_zip_archive);
+
+    // A zip archive stores the aliases of the run it comes from, reserved ones included
+    let reserved = RESERVED_ALIASES.iter().filter(|it| project.aliases.contains_key(**it)).collect::<Vec<_>>();
+    if !is_zip_archive && !reserved.is_empty() {
+        error!(
+            "{} {}: whitesmith defines {} from the location of the project. Rename the alias in the configuration.",
+            reserved.iter().map(|it| it.to_string()).collect::<Vec<_>>().join(", "),
+            if reserved.len() == 1 { "is a reserved alias" } else { "are reserved aliases" },
+            if reserved.len() == 1 { "it" } else { "them" },
+        );
+        std::process::exit(1);
+    }
+    project.aliases.insert(String::from("PROJECT"), project.working_directory.to_owned().parse().unwrap());
+    project.aliases.insert(String::from("SOURCES"), project.source_directory.to_owned().parse().unwrap());
+    project.aliases.insert(String::from("LOGS"), project.log_directory.to_owned().parse().unwrap());
+    project.aliases.insert(String::from("SUMMARY_FILE"), project.summary_file.to_owned().parse().unwrap());
+
+    project.init();
+}
+
+/// Saves the project, with its overrides applied, in the working directory to know what an action ran with.
+pub fn write_effective_configuration(project: &Project, file_name: &str) {
+    if let Ok(file) = File::create(Path::new(&project.working_directory).join(file_name)) {
+        let writer = BufWriter::new(file);
+        ron::ser::to_writer_pretty(writer, project, PrettyConfig::default())
+            .expect("Cannot serialize the project file to ron");
+    }
+}
+
+/// Content and format of a configuration file, or of the configuration stored in a results archive
+pub fn read_configuration(path: &Path, is_zip_archive: bool) -> (String, ConfigurationFormat) {
+    // The configuration stored in a zip archive is always written in ron
+    let format = if is_zip_archive {
+        Some(ConfigurationFormat::Ron)
+    } else {
+        ConfigurationFormat::of(path)
+    };
+    let format = format.unwrap_or_else(|| {
+        error!("Unsupported configuration file {}, expected a .ron, .toml, .yaml, .yml or .zip file", path.display());
+        std::process::exit(1);
+    });
+
+    let mut config_file = File::open(path)
+        .unwrap_or_else(|_| panic!("Cannot open the configuration file '{:?}'. Maybe the file doesn't exists or the permissions are too restrictive.", path));
+
+    let mut configuration = String::new();
+    if is_zip_archive {
+        let mut archive = zip::ZipArchive::new(&mut config_file)
+            .expect("Cannot read the zip file");
+        archive.by_name("configuration.ron")
+            .expect("Cannot read the configuration.ron file. Maybe the archive wasn't build by whitesmith")
+            .read_to_string(&mut configuration)
+            .expect("Cannot read the configuration.ron file of the archive");
+    } else {
+        config_file.read_to_string(&mut configuration)
+            .expect("Cannot read the configuration file");
+    }
+    (configuration, format)
+}
+
+/// Summary of the results of a configuration file, a results archive or a directory containing a configuration
+pub fn read_other_summary(path: &Path) -> Option<String> {
+    let path = locate_configuration(path);
+    let is_zip_archive = is_zip_archive(&path);
+    let (configuration, format) = read_configuration(&path, is_zip_archive);
+    let (project, _) = parse_project(&path, &configuration, format);
+    let layout = project.layout.resolve(&path, &project.aliases);
+    read_summary(&path, &summary_file(&path, &project.versioning, &layout, is_zip_archive), is_zip_archive)
+}
+
+pub fn read_summary(path: &Path, summary_file: &str, is_zip_archive: bool) -> Option<String> {
+    if is_zip_archive {
+        read_zip_entry(path, summary_file)
+    } else {
+        fs::read_to_string(summary_file).ok()
+    }
+}
+
+/// Looks for `configuration.<ext>` then `whitesmith.<ext>` inside `directory`.
+/// The working, source and log directories are then created next to the file, in `directory`.
+fn find_configuration(directory: &Path) -> PathBuf {
+    CONFIGURATION_NAMES.iter()
+        .flat_map(|name| CONFIGURATION_EXTENSIONS.iter().map(move |extension| directory.join(format!("{}.{}", name, extension))))
+        .find(|candidate| candidate.is_file())
+        .unwrap_or_else(|| {
+            error!(
+                "Cannot find a configuration file in {}, expected {{{}}}.{{{}}}",
+                directory.display(), CONFIGURATION_NAMES.join(","), CONFIGURATION_EXTENSIONS.join(",")
+            );
+            std::process::exit(1);
+        })
+}
+
+/// Loads the files included by `path`, in order, with their own includes already merged.
+fn resolve_includes(path: &Path, includes: &[PathBuf], visited: &mut Vec<PathBuf>) -> Include {
+    let mut merged = Include { include: Vec::new(), aliases: Aliases::new(), experiments: Vec::new() };
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
+    for include in includes {
+        let include_path = directory.join(include);
+        let canonical = include_path.canonicalize().unwrap_or_else(|_| {
+            error!("Cannot open the included file {}", include_path.display());
+            std::process::exit(1);
+        });
+        if visited.contains(&canonical) {
+            error!("{} is included recursively", include_path.display());
+            std::process::exit(1);
+        }
+        visited.push(canonical);
+
+        let format = ConfigurationFormat::of(&include_path).unwrap_or_else(|| {
+            error!("Unsupported included file {}, expected a .ron, .toml, .yaml or .yml file", include_path.display());
+            std::process::exit(1);
+        });
+        let content = fs::read_to_string(&include_path)
+            .unwrap_or_else(|_| panic!("Cannot read the included file {:?}", include_path));
+        let mut include = parse_configuration::<Include>(&include_path, &content, format);
+        let nested = resolve_includes(&include_path, &std::mem::take(&mut include.include), visited);
+        merge_include(&mut include.aliases, &mut include.experiments, nested);
+
+        visited.pop();
+
+        for (key, value) in include.aliases {
+            if merged.aliases.insert(key.clone(), value).is_some() {
+                warn!("The alias {} of {} overrides the one of a previous include", key, include_path.display());
+            }
+        }
+        merged.experiments.extend(include.experiments);
+    }
+    merged
+}
+
+/// Adds the computations of `included` after `experiments`. The aliases already in `aliases` are kept.
+fn merge_include(aliases: &mut Aliases, experiments: &mut Vec<Job>, included: Include) {
+    for (key, value) in included.aliases {
+        aliases.entry(key).or_insert(value);
+    }
+    experiments.extend(included.experiments);
+}
+
+/// Parses the configuration, exiting with the position and the line of the error when it is invalid.
+pub fn parse_configuration<T: DeserializeOwned>(path: &Path, configuration: &str, format: ConfigurationFormat) -> T {
+    match format {
+        ConfigurationFormat::Ron => ron::de::from_str::<T>(configuration).unwrap_or_else(|e| {
+            error!("Cannot parse the configuration file {}:{}: {}", path.display(), e.position, e.code);
+            if let Some(line) = configuration.lines().nth(e.position.line.saturating_sub(1)) {
+                eprintln!("{}", line);
+                eprintln!("{:>1$}", "^", e.position.col);
+            }
+            std::process::exit(1);
+        }),
+        // Both errors already display the position of the problem
+        ConfigurationFormat::Toml => toml::from_str::<T>(configuration).unwrap_or_else(|e| {
+            error!("Cannot parse the configuration file {}: {}", path.display(), e);
+            std::process::exit(1);
+        }),
+        ConfigurationFormat::Yaml => serde_yaml::from_str::<T>(configuration).unwrap_or_else(|e| {
+            error!("Cannot parse the configuration file {}: {}", path.display(), e);
+            std::process::exit(1);
+        }),
+    }
+}
+
+pub fn write_migrated_configuration(path: &Path, project: &Project, format: ConfigurationFormat) {
+    let mut backup = path.as_os_str().to_os_string();
+    backup.push(".bak");
+    fs::copy(path, &backup)
+        .unwrap_or_else(|_| panic!("Cannot back up the configuration file to {:?}", backup));
+    let serialized_project = match format {
+        ConfigurationFormat::Ron => ron::ser::to_string_pretty(project, PrettyConfig::default())
+            .map_err(|e| e.to_string()),
+        ConfigurationFormat::Toml => toml::to_string_pretty(project)
+            .map_err(|e| e.to_string()),
+        ConfigurationFormat::Yaml => serde_yaml::to_string(project)
+            .map_err(|e| e.to_string()),
+    }.expect("Cannot serialize the migrated configuration");
+    fs::write(path, serialized_project)
+        .unwrap_or_else(|_| panic!("Cannot write the migrated configuration to {:?}", path));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use super::{open_project, read_other_summary};
+
+    /// Configuration `other.ron` of `version`, in a directory of its own that is emptied first
+    fn other_configuration(name: &str, version: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("whitesmith-other-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let configuration = directory.join("other.ron");
+        fs::write(&configuration, format!(r#"(
+            version: ({}),
+            versioning: (url: "file:/dev/null"),
+            commands: (build: "true"),
+            timeout: Some("10m"),
+            experiments: [(name: "first", cmd: "true")],
+        )"#, version)).unwrap();
+        configuration
+    }
+
+    #[test]
+    fn the_summary_of_an_older_configuration_is_read() {
+        let configuration = other_configuration("older", "0, 5, 3");
+        let project = open_project(&configuration);
+        fs::write(&project.summary_file, "name\nfirst\n").unwrap();
+
+        let summary = read_other_summary(&configuration);
+        fs::remove_dir_all(configuration.parent().unwrap()).unwrap();
+
+        assert_eq!(summary.as_deref(), Some("name\nfirst\n"));
+    }
+
+    #[test]
+    fn the_summary_of_an_unsupported_configuration_is_refused() {
+        let configuration = other_configuration("unsupported", "0, 4, 0");
+        let summary = std::panic::catch_unwind(|| read_other_summary(&configuration));
+        fs::remove_dir_all(configuration.parent().unwrap()).unwrap();
+
+        assert!(summary.is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use crate::model::job::checkpoints::Checkpoints;
+
+/// The process is killed once it has written this many times `max_bytes` to one of its logs
+pub const HARD_LIMIT_FACTOR: u64 = 10;
+
+/// Log file that never grows beyond `max_bytes` and keeps the tail of the output.
+/// Once the file is full it is moved aside and a new one is started, `finish` then keeps the last
+/// `max_bytes` of both, so that the disk usage stays below twice the limit while the process runs.
+pub struct CappedLog {
+    path: PathBuf,
+    file: File,
+    max_bytes: u64,
+    part_len: u64,
+    total: u64,
+    rotated: bool,
+    killed: bool,
+}
+
+impl CappedLog {
+    pub fn new(path: &Path, file: File, max_bytes: u64) -> Self {
+        CappedLog { path: path.to_owned(), file, max_bytes: max_bytes.max(1), part_len: 0, total: 0, rotated: false, killed: false }
+    }
+
+    /// Whether the process wrote so much that it should be stopped
+    pub fn exceeds_hard_limit(&self) -> bool {
+        self.total > self.max_bytes.saturating_mul(HARD_LIMIT_FACTOR)
+    }
+
+    fn previous_part(&self) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".previous");
+        PathBuf::from(path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        fs::rename(&self.path, self.previous_part())?;
+        self.file = File::create(&self.path)?;
+        self.part_len = 0;
+        self.rotated = true;
+        Ok(())
+    }
+
+    /// Rewrites the log with the last `max_bytes` of the output, preceded by the number of dropped bytes.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if !self.rotated {
+            return Ok(());
+        }
+
+        let previous_part = self.previous_part();
+        let mut previous = File::open(&previous_part)?;
+        let kept_from_previous = self.max_bytes - self.part_len;
+        let previous_len = previous.metadata()?.len();
+        previous.seek(SeekFrom::Start(previous_len.saturating_sub(kept_from_previous)))?;
+
+        let mut truncated = previous_part.clone().into_os_string();
+        truncated.push(".truncated");
+        let truncated = PathBuf::from(truncated);
+        {
+            let mut output = File::create(&truncated)?;
+            writeln!(output, "[whitesmith] log truncated, {} bytes dropped, only the last {} are kept",
+                     self.total - self.max_bytes, self.max_bytes)?;
+            if self.killed {
+                writeln!(output, "[whitesmith] the process was killed for writing more than {} bytes", self.max_bytes * HARD_LIMIT_FACTOR)?;
+            }
+            io::copy(&mut previous, &mut output)?;
+            io::copy(&mut File::open(&self.path)?, &mut output)?;
+        }
+        fs::rename(&truncated, &self.path)?;
+        fs::remove_file(&previous_part)
+    }
+}
+
+impl Write for CappedLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.part_len == self.max_bytes {
+            self.rotate()?;
+        }
+        let len = (buf.len() as u64).min(self.max_bytes - self.part_len) as usize;
+        let written = self.file.write(&buf[..len])?;
+        self.part_len += written as u64;
+        self.total += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Destination of an output of a process: the log file, possibly capped
+pub enum LogSink {
+    Plain(File),
+    Capped(CappedLog),
+}
+
+impl LogSink {
+    fn exceeds_hard_limit(&self) -> bool {
+        matches!(self, LogSink::Capped(log) if log.exceeds_hard_limit())
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            LogSink::Plain(mut file) => file.flush(),
+            LogSink::Capped(log) => log.finish(),
+        }
+    }
+}
+
+impl Write for LogSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            LogSink::Plain(file) => file.write(buf),
+            LogSink::Capped(log) => log.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            LogSink::Plain(file) => file.flush(),
+            LogSink::Capped(log) => log.flush(),
+        }
+    }
+}
+
+/// Echoes the lines of an output of a process to the terminal, after `prefix`
+pub struct Tee {
+    pub prefix: String,
+    pub to_stderr: bool,
+}
+
+impl Tee {
+    fn print(&self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        if self.to_stderr {
+            eprintln!("{}{}", self.prefix, line);
+        } else {
+            println!("{}{}", self.prefix, line);
+        }
+    }
+}
+
+/// Copies the output of a process into its log until the process closes it, and echoes its lines with `tee`.
+/// Each line is also given to the `checkpoints`.
+/// `on_hard_limit` is called once if the output exceeds the hard limit, the remaining output is then discarded.
+pub fn pump<R: Read>(mut reader: R, mut sink: LogSink, tee: Option<Tee>, checkpoints: Option<&Checkpoints>, on_hard_limit: impl FnOnce()) -> io::Result<()> {
+    let mut on_hard_limit = Some(on_hard_limit);
+    let mut buffer = [0u8; 8192];
+    let mut partial_line = Vec::new();
+    loop {
+        let len = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if sink.exceeds_hard_limit() {
+            if let (Some(on_hard_limit), LogSink::Capped(log)) = (on_hard_limit.take(), &mut sink) {
+                log.killed = true;
+                on_hard_limit();
+            }
+            continue;
+        }
+        sink.write_all(&buffer[..len])?;
+
+        if tee.is_some() || checkpoints.is_some() {
+            partial_line.extend_from_slice(&buffer[..len]);
+            while let Some(end) = partial_line.iter().position(|&it| it == b'\n') {
+                on_line(&partial_line[..end], &tee, checkpoints);
+                partial_line.drain(..=end);
+            }
+        }
+    }
+    if !partial_line.is_empty() {
+        on_line(&partial_line, &tee, checkpoints);
+    }
+    sink.finish()
+}
+
+fn on_line(line: &[u8], tee: &Option<Tee>, checkpoints: Option<&Checkpoints>) {
+    if let Some(tee) = tee {
+        tee.print(line);
+    }
+    if let Some(checkpoints) = checkpoints {
+        checkpoints.observe(line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn log_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("whitesmith-{}-{}.log", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn capped(path: &Path, max_bytes: u64) -> LogSink {
+        LogSink::Capped(CappedLog::new(path, File::create(path).unwrap(), max_bytes))
+    }
+
+    #[test]
+    fn short_output_is_kept_as_is() {
+        let path = log_path("short");
+        pump(&b"first\nsecond\n"[..], capped(&path, 100), None, None, || panic!("Not killed")).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(content, "first\nsecond\n");
+    }
+
+    #[test]
+    fn long_output_keeps_its_tail() {
+        let path = log_path("tail");
+        let output = (0..50).map(|i| format!("{:02}\n", i)).collect::<String>();
+        pump(output.as_bytes(), capped(&path, 30), None, None, || panic!("Not killed")).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let previous_part = Path::new(&format!("{}.previous", path.display())).exists();
+        fs::remove_file(&path).unwrap();
+        let (header, tail) = content.split_once('\n').unwrap();
+        assert_eq!(header, "[whitesmith] log truncated, 120 bytes dropped, only the last 30 are kept");
+        assert_eq!(tail, &output[output.len() - 30..]);
+        assert!(!previous_part);
+    }
+
+    #[test]
+    fn output_beyond_the_hard_limit_kills_once_and_is_discarded() {
+        let path = log_path("killed");
+        let killed = Cell::new(0);
+        // Read in chunks of 8 KiB: the first two are written, which exceeds the hard limit, the next ones are discarded
+        let output = vec![b'x'; 64 * 1024];
+        pump(&output[..], capped(&path, 1000), None, None, || killed.set(killed.get() + 1)).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(killed.get(), 1);
+        let mut lines = content.lines();
+        assert_eq!(lines.next(), Some("[whitesmith] log truncated, 15384 bytes dropped, only the last 1000 are kept"));
+        assert_eq!(lines.next(), Some("[whitesmith] the process was killed for writing more than 10000 bytes"));
+        assert_eq!(lines.next().map(str::len), Some(1000));
+    }
+
+    #[test]
+    fn plain_log_is_not_capped() {
+        let path = log_path("plain");
+        let output = vec![b'x'; 64 * 1024];
+        pump(&output[..], LogSink::Plain(File::create(&path).unwrap()), None, None, || panic!("Not killed")).unwrap();
+
+        let len = fs::metadata(&path).unwrap().len();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(len, 64 * 1024);
+    }
+}
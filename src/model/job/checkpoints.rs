@@ -0,0 +1,49 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use regex::Regex;
+
+/// Named regexes looked for in the output of a computation. A checkpoint is reached at the first line
+/// it matches, the time spent in a phase is the time between a checkpoint and the previous one reached.
+pub struct Checkpoints {
+    patterns: Vec<(String, Regex)>,
+    start: Mutex<Instant>,
+    reached: Mutex<Vec<(String, Instant)>>,
+}
+
+impl Checkpoints {
+    pub fn new(patterns: Vec<(String, Regex)>) -> Self {
+        Checkpoints { patterns, start: Mutex::new(Instant::now()), reached: Mutex::new(Vec::new()) }
+    }
+
+    /// Forgets the checkpoints reached so far and measures the next ones from now
+    pub fn restart(&self) {
+        *self.start.lock().unwrap() = Instant::now();
+        self.reached.lock().unwrap().clear();
+    }
+
+    /// Called with each line of the output as soon as it is read
+    pub fn observe(&self, line: &[u8]) {
+        let now = Instant::now();
+        let line = String::from_utf8_lossy(line);
+        let mut reached = self.reached.lock().unwrap();
+        for (name, regex) in &self.patterns {
+            if !reached.iter().any(|(it, _)| it == name) && regex.is_match(&line) {
+                reached.push((name.clone(), now));
+            }
+        }
+    }
+
+    /// Time spent before each checkpoint reached since the previous one, or since the start for the first one
+    pub fn durations(&self) -> Vec<(String, Duration)> {
+        let mut previous = *self.start.lock().unwrap();
+        let mut reached = self.reached.lock().unwrap().clone();
+        reached.sort_by_key(|(_, at)| *at);
+        reached.into_iter()
+            .map(|(name, at)| {
+                let duration = at.saturating_duration_since(previous);
+                previous = at;
+                (name, duration)
+            })
+            .collect()
+    }
+}
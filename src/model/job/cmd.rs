@@ -1,22 +1,66 @@
-use fs::OpenOptions;
 use std::cmp::max;
-use std::fs;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::path::Path;
+use bytesize::ByteSize;
+use chrono::{DateTime, Local, SecondsFormat};
 use threadpool::ThreadPool;
-use crate::ABORT;
+use std::thread;
+use std::time::Duration;
 use crate::model::aliases::Aliases;
+use crate::model::commands::BuildSteps;
 use crate::model::project::Project;
+use crate::model::run::RunState;
+use log::info;
 use serde::{Serialize, Deserialize};
 use crate::model::computation_result::ComputationResult;
 use crate::model::job::cmd_env::CmdEnv;
-use crate::model::output::{Iterations, OutputLine, Seconds};
+use crate::tools::{available_cores, hostname};
+use crate::tools::affinity::pin_current_thread;
+use crate::model::output::{Iterations, OutputLine, SummaryWriter, Time};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Cmd {
     pub name: String,
     pub cmd: String,
+    /// Directory the command runs in, relative to the source directory. Aliases are allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Overrides the `before` hook of the commands for this computation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// Overrides the `after` hook of the commands for this computation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// Build steps of the computation, run once in its directory before its first iteration, e.g. `"make solver_{N}"`.
+    /// When one of them fails, the computation is recorded as `BuildError` and the run goes on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildSteps>,
+    /// Script deciding whether the computation is relevant, e.g. `"command -v nvidia-smi"`.
+    /// The computation is recorded as `Skipped` when it exits with a non-zero status. Aliases are allowed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+    /// Summary columns filled with the last match of a regex in the logs of each run, e.g.
+    /// `{"objective": "objective: ([0-9.]+)"}`. The first group is kept if the regex has one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, String>,
+    /// Summary columns with the time spent before the first line matching a regex, e.g.
+    /// `{"parsing": "^parsed", "solving": "^solved"}`. The time is counted from the previous checkpoint
+    /// reached in the output, or from the start of the command.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checkpoints: BTreeMap<String, String>,
+    /// Environment variables of the command, its hooks and its build steps, e.g. `{"OMP_NUM_THREADS": "{T}"}`.
+    /// Aliases are allowed. Each variable is recorded in a summary column of the same name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Names of the computations that must succeed before this one starts, e.g. `["generate_{N}"]`.
+    /// Aliases are allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Overrides the `max_log_bytes` of the project for this computation, e.g. `Some("1GB")` for a verbose one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_log_bytes: Option<ByteSize>,
 }
 
 impl Cmd {
@@ -28,54 +72,107 @@ impl Cmd {
         })
     }
 
-    pub(crate) fn exec_on_pool(&self, _pool: ThreadPool, project: Project, aliases: Aliases) {
+    pub(crate) fn exec_on_pool(&self, _pool: ThreadPool, project: Project, aliases: Aliases, summary: &SummaryWriter, state: &RunState) {
         let cmd_env = CmdEnv { cmd: self.clone(), project, aliases, };
+        Cmd::exec(&cmd_env, summary, state);
+    }
 
-        let mut summary_file = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(&cmd_env.summary_file())
-            .expect("Cannot open summary file");
+    /// Records a computation that cannot run since one of its dependencies did not succeed
+    pub(crate) fn skip(cmd_env: &CmdEnv, summary: &SummaryWriter) {
+        if !cmd_env.project.is_selected(&cmd_env.name()) || !cmd_env.try_lock() {
+            return;
+        }
+        record(cmd_env, summary, ComputationResult::Skipped(Duration::ZERO), Local::now(), None);
+        cmd_env.add_skipped_tag();
+        cmd_env.add_done_tag();
+    }
 
-        if *ABORT.lock().unwrap() { return; }
-        let exp_log_directory = cmd_env.log_dir();
+    /// Runs the iterations of a computation, unless it was already run or is not selected
+    pub(crate) fn exec(cmd_env: &CmdEnv, summary: &SummaryWriter, state: &RunState) {
+        wait_while_paused(state);
+        if state.is_aborted() { return; }
+        if !cmd_env.project.is_selected(&cmd_env.name()) { return; }
         if cmd_env.try_lock() {
+            if cmd_env.project.pin_cpus {
+                pin_current_thread();
+            }
+            if cmd_env.cmd.build.is_some() {
+                let started_at = Local::now();
+                if let Err((computation_result, log)) = cmd_env.build() {
+                    info!("{}End {} {:?}", cmd_env.prefix(), cmd_env.name(), computation_result);
+                    record(cmd_env, summary, computation_result, started_at, Some(&log));
+                    cmd_env.add_err_tag();
+                    cmd_env.add_done_tag();
+                    return;
+                }
+            }
+            let hostname = hostname();
             for i in 1..=max(1, cmd_env.project.iterations) {
-                eprintln!("Start {} {}/{} ", cmd_env.name(), i, cmd_env.project.iterations);
-                let stderr_file = exp_log_directory.clone().join(format!("run_{}.stderr", i));
-                let computation_result = cmd_env.run(&stderr_file);
-                eprintln!("End {} {}/{}  {:?}", cmd_env.name(), i, cmd_env.project.iterations, computation_result);
-
-                let mut csv_writer = csv::WriterBuilder::new()
-                    .has_headers(false)
-                    .from_writer(&mut summary_file);
+                // Left in progress, the remaining iterations run with --with-in-progress
+                if state.is_aborted() { return; }
+                info!("{}Start {} {}/{} ", cmd_env.prefix(), cmd_env.name(), i, cmd_env.project.iterations);
+                let (stderr_file, stdout_file) = cmd_env.log_files(i);
+                let started_at = Local::now();
+                let checkpoints = cmd_env.checkpoints();
+                let computation_result = cmd_env.run(&stderr_file, &stdout_file, checkpoints.as_ref(), &state.children);
+                info!("{}End {} {}/{}  {:?}", cmd_env.prefix(), cmd_env.name(), i, cmd_env.project.iterations, computation_result);
 
-                let (status, time) = match computation_result {
-                    ComputationResult::Ok(duration) => ("Ok", Seconds(duration.as_secs_f64())),
-                    ComputationResult::Timeout(duration) => ("Timeout", Seconds(duration.as_secs_f64())),
-                    ComputationResult::Error(duration) => ("Error", Seconds(duration.as_secs_f64())),
+                let (status, duration) = match computation_result {
+                    ComputationResult::Ok(duration, _) => ("Ok", duration),
+                    ComputationResult::Timeout(duration, _) => ("Timeout", duration),
+                    ComputationResult::Error(duration, _) => ("Error", duration),
+                    ComputationResult::BeforeError(duration) => ("BeforeError", duration),
+                    ComputationResult::Skipped(duration) => ("Skipped", duration),
+                    ComputationResult::BuildError(duration) => ("BuildError", duration),
                 };
 
                 let outline = OutputLine {
                     name: cmd_env.name(),
                     status: status.to_string(),
-                    time,
-                    iterations: Iterations(i, cmd_env.project.iterations)
+                    time: Time::new(duration, cmd_env.project.raw_seconds),
+                    user_time: computation_result.cpu_time().map(|it| Time::new(it.user, cmd_env.project.raw_seconds)),
+                    system_time: computation_result.cpu_time().map(|it| Time::new(it.system, cmd_env.project.raw_seconds)),
+                    iterations: Iterations(i, cmd_env.project.iterations),
+                    started_at: started_at.to_rfc3339_opts(SecondsFormat::Secs, false),
+                    hostname: hostname.clone(),
+                    threads: cmd_env.project.nb_threads.unwrap_or_else(available_cores),
+                    cores: available_cores(),
+                    measure_mode: cmd_env.project.measure_mode,
+                    log: stderr_file.strip_prefix(&cmd_env.project.log_directory)
+                        .unwrap_or(&stderr_file)
+                        .to_string_lossy()
+                        .into_owned(),
+                    partial_result: if computation_result.is_timeout() {
+                        cmd_env.partial_result(&stderr_file, &stdout_file).unwrap_or_default()
+                    } else {
+                        String::new()
+                    },
+                    metrics: {
+                        let mut metrics = cmd_env.metrics(&stderr_file, &stdout_file);
+                        for (name, duration) in checkpoints.map(|it| it.durations()).unwrap_or_default() {
+                            metrics.insert(name, Time::new(duration, cmd_env.project.raw_seconds).to_string());
+                        }
+                        metric_cells(cmd_env, summary, metrics)
+                    },
                 };
 
-                csv_writer.serialize(outline)
-                    .unwrap();
+                summary.write_line(&outline)
+                    .expect("Cannot write to the summary file");
+                summary.record(outline.name, computation_result);
 
                 if computation_result.is_err() {
                     cmd_env.add_err_tag();
                     if cmd_env.project.debug {
-                        eprintln_file(&stderr_file);
+                        eprintln_file(&stderr_file, &cmd_env.prefix());
                         return;
                     } else {
                         break;
                     }
                 } else if computation_result.is_timeout() {
                     cmd_env.add_timeout_tag();
+                } else if computation_result.is_skipped() {
+                    cmd_env.add_skipped_tag();
+                    break;
                 }
             }
             cmd_env.add_done_tag();
@@ -84,13 +181,51 @@ impl Cmd {
 }
 
 
-fn eprintln_file(path: &PathBuf) {
+/// Writes the row of a computation that did not run its command
+fn record(cmd_env: &CmdEnv, summary: &SummaryWriter, result: ComputationResult, started_at: DateTime<Local>, log: Option<&Path>) {
+    let outline = OutputLine {
+        name: cmd_env.name(),
+        status: result.to_string(),
+        time: Time::new(result.duration(), cmd_env.project.raw_seconds),
+        user_time: None,
+        system_time: None,
+        iterations: Iterations(1, cmd_env.project.iterations),
+        started_at: started_at.to_rfc3339_opts(SecondsFormat::Secs, false),
+        hostname: hostname(),
+        threads: cmd_env.project.nb_threads.unwrap_or_else(available_cores),
+        cores: available_cores(),
+        measure_mode: cmd_env.project.measure_mode,
+        log: log.map(|it| it.strip_prefix(&cmd_env.project.log_directory).unwrap_or(it).to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        partial_result: String::new(),
+        metrics: metric_cells(cmd_env, summary, HashMap::new()),
+    };
+    summary.write_line(&outline)
+        .expect("Cannot write to the summary file");
+    summary.record(outline.name, result);
+}
+
+/// Cells of the metric columns of the summary, with the environment variables of the computation
+fn metric_cells(cmd_env: &CmdEnv, summary: &SummaryWriter, mut metrics: HashMap<String, String>) -> Vec<String> {
+    metrics.extend(cmd_env.env());
+    summary.metrics().iter()
+        .map(|column| metrics.remove(column).unwrap_or_default())
+        .collect()
+}
+
+fn wait_while_paused(state: &RunState) {
+    while state.is_paused() && !state.is_aborted() {
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+fn eprintln_file(path: &Path, prefix: &str) {
     let file_buf = BufReader::new(File::open(path)
-        .expect(&format!("Cannot open `{:?}`", path)));
-    eprintln!("```");
+        .unwrap_or_else(|_| panic!("Cannot open `{:?}`", path)));
+    eprintln!("{}```", prefix);
     for line in file_buf.lines() {
         let line = line.unwrap();
-        eprintln!("{}", &line);
+        eprintln!("{}{}", prefix, &line);
     }
-    eprintln!("```");
+    eprintln!("{}```", prefix);
 }
\ No newline at end of file
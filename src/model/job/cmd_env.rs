@@ -1,17 +1,79 @@
 use crate::model::project::{Project};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs;
-use std::fs::{OpenOptions};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::Instant;
 use chrono::{Local, DateTime};
+use log::{info, warn};
 use crate::model::aliases::Aliases;
-use crate::model::commands::restore_str;
+use crate::model::children::Children;
+use crate::model::commands::{resolve_cwd, restore_str, Logs};
 use crate::model::computation_result::ComputationResult;
 use crate::model::job::cmd::Cmd;
+use crate::model::job::checkpoints::Checkpoints;
+use clap::ValueEnum;
+use colored::{Color, ColoredString, Colorize};
+use crate::tools::sanitize_file_name;
+use regex::Regex;
 
 pub struct Tag {
     pub name: &'static str,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    NotStarted,
+    Running,
+    Done,
+    Failed,
+    Timeout,
+    /// Its `when` condition failed or one of its dependencies did not succeed, it is evaluated again by the next run
+    Skipped,
+}
+
+impl Status {
+    pub const ALL: [Status; 6] = [Status::NotStarted, Status::Running, Status::Done, Status::Failed, Status::Timeout, Status::Skipped];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Status::NotStarted => "not_started",
+            Status::Running => "running",
+            Status::Done => "done",
+            Status::Failed => "failed",
+            Status::Timeout => "timeout",
+            Status::Skipped => "skipped",
+        }
+    }
+
+    pub fn colored(&self) -> ColoredString {
+        match self {
+            Status::NotStarted => "No started".black(),
+            Status::Running => "Running".blue(),
+            Status::Done => "Done".green(),
+            Status::Failed => "Failed".red(),
+            Status::Timeout => "Timeout".yellow(),
+            Status::Skipped => "Skipped".cyan(),
+        }
+    }
+}
+
+/// How the lines printed on behalf of a computation are tagged with its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PrefixStyle {
+    Plain,
+    /// Each computation gets its own color
+    Color,
+}
+
+const PREFIX_COLORS: [Color; 10] = [
+    Color::Cyan, Color::Magenta, Color::Yellow, Color::Green, Color::Blue,
+    Color::BrightCyan, Color::BrightMagenta, Color::BrightYellow, Color::BrightGreen, Color::BrightBlue,
+];
+
 pub struct CmdEnv {
     pub cmd: Cmd,
     pub project: Project,
@@ -23,33 +85,161 @@ impl CmdEnv {
     pub(crate) const ERR_TAG: Tag = Tag { name: "_err" };
     pub(crate) const TIMEOUT_TAG: Tag = Tag { name: "_timeout" };
     pub(crate) const DONE_TAG: Tag = Tag { name: "_done" };
+    pub(crate) const SKIPPED_TAG: Tag = Tag { name: "_skipped" };
 
     pub fn name(&self) -> String {
         restore_str(&self.cmd.name, &self.aliases)
     }
 
-    pub fn summary_file(&self) -> &String {
-        &self.project.summary_file
-    }
-
-    pub fn run(&self, stderr_file: &PathBuf) -> ComputationResult {
+    pub fn run(&self, stderr_file: &Path, stdout_file: &Path, checkpoints: Option<&Arc<Checkpoints>>, children: &Children) -> ComputationResult {
+        // The logs kept from a previous evaluation of a skipped computation are replaced by the ones of this one
+        for log_file in [stderr_file, stdout_file] {
+            if let Err(e) = fs::remove_file(log_file) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    panic!("Cannot remove the previous log file {:?}: {}", log_file, e);
+                }
+            }
+        }
         let mut open_mode = OpenOptions::new();
         open_mode.create_new(true)
             .write(true)
             .append(true);
 
-        self.project.commands.run_exec(
-            &self.project.source_directory,
-            &self.aliases,
-            &self.cmd.cmd,
-            open_mode.open(stderr_file).expect("Cannot create stderr file"),
-            self.project.global_timeout,
-        )
+        let err_file = open_mode.open(stderr_file).expect("Cannot create stderr file");
+        let out_file = open_mode.open(stdout_file).expect("Cannot create stdout file");
+        let working_directory = self.working_directory();
+        let commands = &self.project.commands;
+
+        if let Some(when) = &self.cmd.when {
+            let clock = Instant::now();
+            match self.run_script(when, &working_directory, &err_file, &out_file) {
+                Ok(status) if status.success() => {}
+                Ok(status) => {
+                    info!("{}Skipping {}, its when condition failed ({})", self.prefix(), self.name(), status);
+                    return ComputationResult::Skipped(clock.elapsed());
+                }
+                Err(e) => {
+                    warn!("{}Skipping {}, its when condition cannot run: {}", self.prefix(), self.name(), e);
+                    return ComputationResult::Skipped(clock.elapsed());
+                }
+            }
+        }
+
+        // The hooks write in the logs of the computation, before and after its own output
+        let before = self.cmd.before.as_ref().or(commands.before.as_ref());
+        if let Some(before) = before {
+            let clock = Instant::now();
+            if !self.run_hook("before", before, &working_directory, &err_file, &out_file) {
+                return ComputationResult::BeforeError(clock.elapsed());
+            }
+        }
+
+        let result = commands.run_exec(
+            self,
+            &working_directory,
+            Logs {
+                err: (stderr_file.to_owned(), err_file.try_clone().expect("Cannot duplicate the stderr file")),
+                out: (stdout_file.to_owned(), out_file.try_clone().expect("Cannot duplicate the stdout file")),
+                max_bytes: self.cmd.max_log_bytes.or(self.project.max_log_bytes).map(|it| it.0),
+                tee: if self.project.tee { Some(self.styled_prefix(self.project.prefix.unwrap_or(PrefixStyle::Plain))) } else { None },
+                checkpoints: checkpoints.map(|it| {
+                    it.restart();
+                    it.clone()
+                }),
+            },
+            children,
+        );
+
+        // Also run after a failure or a timeout, so that the cleanup always happens
+        let after = self.cmd.after.as_ref().or(commands.after.as_ref());
+        if let Some(after) = after {
+            if !self.run_hook("after", after, &working_directory, &err_file, &out_file) {
+                warn!("{}The after hook of {} failed", self.prefix(), self.name());
+            }
+        }
+
+        result
+    }
+
+    /// Runs a `before` or `after` hook with its output appended to the logs of the computation
+    fn run_hook(&self, hook: &str, script: &str, working_directory: &str, err_file: &File, out_file: &File) -> bool {
+        info!("{}{} $ {:?}", self.prefix(), hook, restore_str(script, &self.aliases));
+        match self.run_script(script, working_directory, err_file, out_file) {
+            Ok(status) if status.success() => true,
+            Ok(status) => {
+                warn!("{}The {} hook failed ({})", self.prefix(), hook, status);
+                false
+            }
+            Err(e) => {
+                warn!("{}Cannot run the {} hook: {}", self.prefix(), hook, e);
+                false
+            }
+        }
+    }
+
+    /// Runs the `build` steps of the computation in order, with their output in `<name>_build.err` and `<name>_build.out`.
+    /// Returns the result and the stderr log of the step that failed.
+    pub fn build(&self) -> Result<(), (ComputationResult, PathBuf)> {
+        let steps = match &self.cmd.build {
+            Some(build) => build.steps(),
+            None => return Ok(()),
+        };
+        let dir = self.log_dir();
+        let stem = format!("{}_build", sanitize_file_name(&self.name()));
+        let (stderr_file, stdout_file) = (dir.join(format!("{}.err", stem)), dir.join(format!("{}.out", stem)));
+        let clock = Instant::now();
+        let logs = File::create(&stderr_file).and_then(|err| Ok((err, File::create(&stdout_file)?)));
+        let (err_file, out_file) = match logs {
+            Ok(logs) => logs,
+            Err(e) => {
+                warn!("{}Cannot create the build logs of {}: {}", self.prefix(), self.name(), e);
+                return Err((ComputationResult::BuildError(clock.elapsed()), stderr_file));
+            }
+        };
+        let working_directory = self.working_directory();
+        for (i, step) in steps.iter().enumerate() {
+            info!("{}build $ {:?}", self.prefix(), restore_str(step, &self.aliases));
+            match self.run_script(step, &working_directory, &err_file, &out_file) {
+                Ok(status) if status.success() => {}
+                Ok(status) => {
+                    warn!("{}The build step {}/{} of {} failed ({}), see {}", self.prefix(), i + 1, steps.len(), self.name(), status, stderr_file.display());
+                    return Err((ComputationResult::BuildError(clock.elapsed()), stderr_file));
+                }
+                Err(e) => {
+                    warn!("{}Cannot run the build step {}/{} of {}: {}", self.prefix(), i + 1, steps.len(), self.name(), e);
+                    return Err((ComputationResult::BuildError(clock.elapsed()), stderr_file));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a script of the computation (a build step, a hook or its `when` condition) with its output appended to its logs
+    fn run_script(&self, script: &str, working_directory: &str, err_file: &File, out_file: &File) -> io::Result<ExitStatus> {
+        Command::new("bash")
+            .current_dir(working_directory)
+            .args(["-c", &restore_str(script, &self.aliases)])
+            .envs(self.env())
+            .stdout(Stdio::from(out_file.try_clone()?))
+            .stderr(Stdio::from(err_file.try_clone()?))
+            .status()
+    }
+
+    /// `env` of the computation with its aliases restored
+    pub fn env(&self) -> Vec<(String, String)> {
+        self.cmd.env.iter()
+            .map(|(name, value)| (name.clone(), restore_str(value, &self.aliases)))
+            .collect()
+    }
+
+    /// Directory the command runs in: its `cwd` resolved against the source directory, or the source directory
+    pub fn working_directory(&self) -> String {
+        resolve_cwd(&self.project.source_directory, self.cmd.cwd.as_deref(), &self.aliases)
     }
 
     pub fn log_dir(&self) -> PathBuf {
         let dir = PathBuf::from(&self.project.log_directory)
-            .join(&self.name());
+            .join(sanitize_file_name(&self.name()));
         if !dir.exists() {
             fs::create_dir_all(&dir)
                 .expect("Log dir already exists");
@@ -57,13 +247,101 @@ impl CmdEnv {
         dir
     }
 
+    /// `[name] ` when the output is prefixed (see `run --prefix`), empty otherwise
+    pub fn prefix(&self) -> String {
+        match self.project.prefix {
+            None => String::new(),
+            Some(style) => self.styled_prefix(style),
+        }
+    }
+
+    fn styled_prefix(&self, style: PrefixStyle) -> String {
+        let name = self.name();
+        let prefix = format!("[{}]", name);
+        let prefix = match style {
+            PrefixStyle::Plain => prefix,
+            PrefixStyle::Color => {
+                // The color only depends on the name, so that a computation keeps it from one run to the other
+                let hash = name.bytes().fold(0usize, |hash, it| hash.wrapping_mul(31).wrapping_add(it as usize));
+                prefix.color(PREFIX_COLORS[hash % PREFIX_COLORS.len()]).to_string()
+            }
+        };
+        prefix + " "
+    }
+
+    /// Files receiving the stderr and stdout of the `iteration`th run, e.g. `<name>_1.err` and `<name>_1.out`
+    pub fn log_files(&self, iteration: u32) -> (PathBuf, PathBuf) {
+        let dir = self.log_dir();
+        let stem = format!("{}_{}", sanitize_file_name(&self.name()), iteration);
+        (dir.join(format!("{}.err", stem)), dir.join(format!("{}.out", stem)))
+    }
+
+    /// Best-so-far result of a run that timed out: the last match of the `partial_result` regex in its
+    /// stderr, then in its stdout. The first group of the regex is kept if it has one.
+    pub fn partial_result(&self, stderr_file: &Path, stdout_file: &Path) -> Option<String> {
+        let regex = self.regex("partial_result", self.project.partial_result.as_ref()?)?;
+        last_match(&regex, &read_logs(&[stderr_file, stdout_file]))
+    }
+
+    /// Values of the `metrics` of the computation found in the logs of a run, in the same way as `partial_result`
+    pub fn metrics(&self, stderr_file: &Path, stdout_file: &Path) -> HashMap<String, String> {
+        if self.cmd.metrics.is_empty() {
+            return HashMap::new();
+        }
+        let logs = read_logs(&[stderr_file, stdout_file]);
+        self.cmd.metrics.iter()
+            .filter_map(|(column, pattern)| {
+                let regex = self.regex(&format!("metric {}", column), pattern)?;
+                last_match(&regex, &logs).map(|value| (column.clone(), value))
+            })
+            .collect()
+    }
+
+    /// The `checkpoints` of the computation to look for in the output of a run, if it has any
+    pub fn checkpoints(&self) -> Option<Arc<Checkpoints>> {
+        if self.cmd.checkpoints.is_empty() {
+            return None;
+        }
+        let patterns = self.cmd.checkpoints.iter()
+            .filter_map(|(name, pattern)| Some((name.clone(), self.regex(&format!("checkpoint {}", name), pattern)?)))
+            .collect();
+        Some(Arc::new(Checkpoints::new(patterns)))
+    }
+
+    fn regex(&self, field: &str, pattern: &str) -> Option<Regex> {
+        match Regex::new(&restore_str(pattern, &self.aliases)) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                warn!("{}Invalid {} regex: {}", self.prefix(), field, e);
+                None
+            }
+        }
+    }
+
     pub fn tag_creation_date(&self, tag: &Tag) -> Option<DateTime<Local>> {
         let done_file = self.log_dir().join(tag.name);
         let creation_date = done_file.metadata()
             .and_then(|meta| meta.created())
             .ok();
 
-        creation_date.map(|it| chrono::DateTime::from(it))
+        creation_date.map(chrono::DateTime::from)
+    }
+
+    /// Current status with the date it was reached, read from the tags of the log directory
+    pub fn status(&self) -> (Status, Option<DateTime<Local>>) {
+        if !self.is_locked() {
+            (Status::NotStarted, None)
+        } else if self.has_err_tag() {
+            (Status::Failed, self.tag_creation_date(&CmdEnv::ERR_TAG))
+        } else if self.has_timeout_tag() {
+            (Status::Timeout, self.tag_creation_date(&CmdEnv::TIMEOUT_TAG))
+        } else if self.has_skipped_tag() {
+            (Status::Skipped, self.tag_creation_date(&CmdEnv::SKIPPED_TAG))
+        } else if self.has_done_tag() {
+            (Status::Done, self.tag_creation_date(&CmdEnv::DONE_TAG))
+        } else {
+            (Status::Running, self.tag_creation_date(&CmdEnv::LOCK_TAG))
+        }
     }
 
     pub fn has_err_tag(&self) -> bool { self.has_tag(&CmdEnv::ERR_TAG) }
@@ -72,6 +350,8 @@ impl CmdEnv {
 
     pub fn has_done_tag(&self) -> bool { self.has_tag(&CmdEnv::DONE_TAG) }
 
+    pub fn has_skipped_tag(&self) -> bool { self.has_tag(&CmdEnv::SKIPPED_TAG) }
+
     pub fn is_locked(&self) -> bool {
         self.has_tag(&CmdEnv::LOCK_TAG)
     }
@@ -88,6 +368,21 @@ impl CmdEnv {
         self.add_tag(&CmdEnv::DONE_TAG)
     }
 
+    pub fn add_skipped_tag(&self) {
+        self.add_tag(&CmdEnv::SKIPPED_TAG)
+    }
+
+    /// Removes the tags of a skipped computation so that the next run evaluates it again, its logs are kept
+    pub fn unlock_skipped(&self) {
+        for tag in [&CmdEnv::DONE_TAG, &CmdEnv::SKIPPED_TAG, &CmdEnv::LOCK_TAG] {
+            if let Err(e) = fs::remove_file(self.log_dir().join(tag.name)) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    panic!("Cannot remove {} file: {}", tag.name, e);
+                }
+            }
+        }
+    }
+
     pub fn try_lock(&self) -> bool {
         let lock_file = self.log_dir().join(CmdEnv::LOCK_TAG.name);
 
@@ -116,7 +411,24 @@ impl CmdEnv {
         OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(tag_file)
-            .expect(&format!("Cannot create {} file", tag.name));
+            .unwrap_or_else(|_| panic!("Cannot create {} file", tag.name));
     }
-}
\ No newline at end of file
+}
+
+fn read_logs(files: &[&Path]) -> Vec<String> {
+    files.iter()
+        .filter_map(|file| fs::read(file).ok())
+        .map(|log| String::from_utf8_lossy(&log).into_owned())
+        .collect()
+}
+
+/// Last match of `regex` in the first log where it appears, its first group if it has one
+fn last_match(regex: &Regex, logs: &[String]) -> Option<String> {
+    logs.iter().find_map(|log| {
+        regex.captures_iter(log).last()
+            .and_then(|captures| captures.get(1).or_else(|| captures.get(0)))
+            .map(|it| it.as_str().to_owned())
+    })
+}
@@ -1,14 +1,17 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use crate::model::aliases::{Alias, Aliases};
 use crate::model::job::{cartesian_product, Job};
+use crate::model::output::SummaryWriter;
 use crate::model::project::Project;
+use crate::model::run::RunState;
 use serde::{Serialize, Deserialize};
 use threadpool::ThreadPool;
 use crate::model::job::cmd_env::CmdEnv;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CmdGroup {
-    pub foreach: HashMap<String, AliasIter>,
+    pub foreach: BTreeMap<String, AliasIter>,
     #[serde(rename="where", default)]
     pub conditions: Vec<String>,
     pub apply: Batch,
@@ -25,7 +28,7 @@ impl AliasIter {
     pub(crate) fn to_vec(&self) -> Vec<Alias> {
         match self {
             AliasIter::Vec(vec) => vec.clone(),
-            AliasIter::ClosedIntRange(range) => (range.start..=range.end_inclusive).map(|it| Alias::Integer(it)).collect::<Vec<_>>()
+            AliasIter::ClosedIntRange(range) => (range.start..=range.end_inclusive).map(Alias::Integer).collect::<Vec<_>>()
         }
     }
 }
@@ -63,18 +66,20 @@ impl CmdGroup {
         }
     }
 
-    pub(crate) fn exec_on_pool(&self, pool: ThreadPool, project: &Project, parent_aliases: &Aliases) {
+    pub(crate) fn exec_on_pool(&self, pool: ThreadPool, project: &Project, parent_aliases: &Aliases, summary: &Arc<SummaryWriter>, state: &Arc<RunState>) {
         let cmds = &self.apply.cmds;
         for captured_context in self.generate_context_combinations(parent_aliases) {
             let captured_pool = pool.clone();
             let captured_jobs = cmds.clone();
             let captured_project = project.clone();
+            let captured_summary = summary.clone();
+            let captured_state = state.clone();
             pool.execute(move || {
                 for job in captured_jobs {
                     let inner_pool = captured_pool.clone();
                     //let inner_project = captured_project.clone();
                     //let inner_context = captured_context.clone();
-                    job.exec_on_pool(inner_pool, &captured_project, &captured_context);
+                    job.exec_on_pool(inner_pool, &captured_project, &captured_context, &captured_summary, &captured_state);
                 }
             })
         }
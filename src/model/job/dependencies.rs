@@ -0,0 +1,253 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{mpsc, Arc};
+use log::warn;
+use threadpool::ThreadPool;
+use crate::model::commands::restore_str;
+use crate::model::job::cmd::Cmd;
+use crate::model::job::cmd_env::{CmdEnv, Status};
+use crate::model::output::SummaryWriter;
+use crate::model::run::RunState;
+
+/// Computations of a project with the ones they depend on (`depends_on`), by index
+pub struct DependencyGraph {
+    cmd_envs: Vec<Arc<CmdEnv>>,
+    dependencies: Vec<Vec<usize>>,
+    dependents: Vec<Vec<usize>>,
+    /// Dependencies that are not computations of the project, by computation
+    unknown: Vec<(usize, String)>,
+}
+
+impl DependencyGraph {
+    pub fn new(cmd_envs: Vec<CmdEnv>) -> Self {
+        let indices = cmd_envs.iter()
+            .enumerate()
+            .map(|(i, cmd_env)| (cmd_env.name(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut dependencies = vec![Vec::new(); cmd_envs.len()];
+        let mut dependents = vec![Vec::new(); cmd_envs.len()];
+        let mut unknown = Vec::new();
+        for (i, cmd_env) in cmd_envs.iter().enumerate() {
+            for dependency in &cmd_env.cmd.depends_on {
+                let dependency = restore_str(dependency, &cmd_env.aliases);
+                match indices.get(&dependency) {
+                    Some(&j) if !dependencies[i].contains(&j) => {
+                        dependencies[i].push(j);
+                        dependents[j].push(i);
+                    }
+                    Some(_) => {}
+                    None => unknown.push((i, dependency)),
+                }
+            }
+        }
+
+        DependencyGraph { cmd_envs: cmd_envs.into_iter().map(Arc::new).collect(), dependencies, dependents, unknown }
+    }
+
+    /// Unknown dependencies and cycles, which would prevent computations from ever starting
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = self.unknown.iter()
+            .map(|(i, dependency)| format!(
+                "The computation {} depends on {}, which is not a computation of the project",
+                self.cmd_envs[*i].name(), dependency,
+            ))
+            .collect::<Vec<_>>();
+        match self.cycle().as_slice() {
+            [] => {}
+            [i] => problems.push(format!("The computation {} depends on itself", self.cmd_envs[*i].name())),
+            cycle => problems.push(format!(
+                "The computations {} depend on each other in a cycle",
+                cycle.iter().map(|&i| self.cmd_envs[i].name()).collect::<Vec<_>>().join(", "),
+            )),
+        }
+        problems
+    }
+
+    /// Computations on a cycle of dependencies, or between two cycles
+    fn cycle(&self) -> Vec<usize> {
+        // Removes the computations that can be ordered from their dependencies first,
+        // then from their dependents, until only the cycles are left
+        let mut left = vec![true; self.cmd_envs.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for i in 0..self.cmd_envs.len() {
+                let free = !self.dependencies[i].iter().any(|&j| left[j])
+                    || !self.dependents[i].iter().any(|&j| left[j]);
+                if left[i] && free {
+                    left[i] = false;
+                    changed = true;
+                }
+            }
+        }
+        (0..self.cmd_envs.len()).filter(|&i| left[i]).collect()
+    }
+
+    /// Runs the computations on `pool`, each one once all of its dependencies succeeded.
+    /// The dependents of a computation that failed or timed out are skipped.
+    pub fn run(&self, pool: &ThreadPool, summary: &Arc<SummaryWriter>, state: &Arc<RunState>) {
+        let mut waiting = self.dependencies.iter().map(Vec::len).collect::<Vec<_>>();
+        let mut ready = (0..self.cmd_envs.len())
+            .filter(|&i| waiting[i] == 0)
+            .collect::<VecDeque<_>>();
+        let mut skipped = vec![false; self.cmd_envs.len()];
+        let (sender, receiver) = mpsc::channel();
+        let mut running = 0;
+
+        loop {
+            while let Some(i) = ready.pop_front() {
+                if state.is_aborted() {
+                    break;
+                }
+                running += 1;
+                let cmd_env = self.cmd_envs[i].clone();
+                let summary = summary.clone();
+                let state = state.clone();
+                let finished = Finished { index: i, cmd_env: cmd_env.clone(), sender: sender.clone() };
+                pool.execute(move || {
+                    // Reports the end of the computation even if it panics
+                    let _finished = finished;
+                    Cmd::exec(&cmd_env, &summary, &state);
+                });
+            }
+            if running == 0 {
+                break;
+            }
+
+            let (i, succeeded) = receiver.recv().expect("Cannot receive the end of a computation");
+            running -= 1;
+            if succeeded {
+                for &j in &self.dependents[i] {
+                    waiting[j] -= 1;
+                    if waiting[j] == 0 {
+                        ready.push_back(j);
+                    }
+                }
+            } else if !state.is_aborted() {
+                self.skip_dependents(i, &mut skipped, summary);
+            }
+        }
+    }
+
+    fn skip_dependents(&self, failed: usize, skipped: &mut [bool], summary: &SummaryWriter) {
+        let mut queue = VecDeque::from(vec![failed]);
+        while let Some(i) = queue.pop_front() {
+            for &j in &self.dependents[i] {
+                if !skipped[j] {
+                    skipped[j] = true;
+                    warn!("Skipping {}, {} did not succeed", self.cmd_envs[j].name(), self.cmd_envs[i].name());
+                    Cmd::skip(&self.cmd_envs[j], summary);
+                    queue.push_back(j);
+                }
+            }
+        }
+    }
+}
+
+/// Sends the end of a computation when dropped
+struct Finished {
+    index: usize,
+    cmd_env: Arc<CmdEnv>,
+    sender: mpsc::Sender<(usize, bool)>,
+}
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        let succeeded = self.cmd_env.status().0 == Status::Done;
+        let _ = self.sender.send((self.index, succeeded));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+    use threadpool::ThreadPool;
+    use crate::model::computation_result::ComputationResult;
+    use crate::model::output::SummaryWriter;
+    use crate::model::project::Project;
+    use crate::model::run::RunState;
+    use super::DependencyGraph;
+
+    /// Graph of the computations `experiments`, whose logs go in `directory`
+    fn graph(experiments: &str, directory: &Path) -> DependencyGraph {
+        let mut project = ron::de::from_str::<Project>(&format!(
+            r#"(version: (0, 6, 2), versioning: (url: ""), commands: (build: ""), experiments: [{}])"#, experiments,
+        )).unwrap();
+        project.source_directory = directory.to_string_lossy().into_owned();
+        project.log_directory = directory.join("logs").to_string_lossy().into_owned();
+        let mut cmd_envs = Vec::new();
+        for job in &project.experiments {
+            job.enqueue(&mut cmd_envs, &project, &project.aliases);
+        }
+        DependencyGraph::new(cmd_envs)
+    }
+
+    #[test]
+    fn dependencies_on_unknown_computations_are_reported() {
+        let graph = graph(r#"
+            (name: "solve", cmd: "true", depends_on: ["generate", "missing"]),
+            (name: "generate", cmd: "true"),
+        "#, Path::new("unused"));
+
+        assert_eq!(graph.problems(), ["The computation solve depends on missing, which is not a computation of the project"]);
+    }
+
+    #[test]
+    fn cycles_are_reported_without_the_computations_around_them() {
+        let cycles = graph(r#"
+            (name: "a", cmd: "true", depends_on: ["c"]),
+            (name: "b", cmd: "true", depends_on: ["a"]),
+            (name: "c", cmd: "true", depends_on: ["b"]),
+            (name: "before", cmd: "true"),
+            (name: "after", cmd: "true", depends_on: ["a", "before"]),
+        "#, Path::new("unused"));
+
+        assert_eq!(cycles.problems(), ["The computations a, b, c depend on each other in a cycle"]);
+
+        let alone = graph(r#"(name: "itself", cmd: "true", depends_on: ["itself"])"#, Path::new("unused"));
+        assert_eq!(alone.problems(), ["The computation itself depends on itself"]);
+    }
+
+    #[test]
+    fn dependencies_may_use_the_aliases_of_the_computation() {
+        let graph = graph(r#"
+            (foreach: {"N": [8, 16]}, apply: (aliases: {}, cmds: [
+                (name: "generate_{N}", cmd: "true"),
+                (name: "solve_{N}", cmd: "true", depends_on: ["generate_{N}"]),
+            ])),
+        "#, Path::new("unused"));
+
+        assert!(graph.problems().is_empty());
+        assert_eq!(graph.dependencies, [vec![], vec![0], vec![], vec![2]]);
+    }
+
+    #[test]
+    fn dependents_of_a_failed_computation_are_skipped() {
+        let directory = std::env::temp_dir().join(format!("whitesmith-dependencies-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let graph = graph(r#"
+            (name: "generate", cmd: "exit 1"),
+            (name: "solve", cmd: "true", depends_on: ["generate"]),
+            (name: "check", cmd: "true", depends_on: ["solve"]),
+            (name: "other", cmd: "true"),
+        "#, &directory);
+        let summary = Arc::new(SummaryWriter::open(&directory.join("summary.csv"), Vec::new()).unwrap());
+
+        graph.run(&ThreadPool::new(2), &summary, &Arc::new(RunState::default()));
+        let report = summary.report();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let result = |name: &str| report.results.iter()
+            .find(|(it, _)| it == name)
+            .map(|(_, result)| *result)
+            .unwrap_or_else(|| panic!("{} has no result", name));
+        assert_eq!(report.results.len(), 4);
+        assert!(matches!(result("generate"), ComputationResult::Error(..)));
+        assert!(matches!(result("solve"), ComputationResult::Skipped(..)));
+        assert!(matches!(result("check"), ComputationResult::Skipped(..)));
+        assert!(matches!(result("other"), ComputationResult::Ok(..)));
+    }
+}
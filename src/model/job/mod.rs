@@ -1,8 +1,12 @@
 pub mod cmd;
 pub mod cmd_group;
 pub mod cmd_env;
+pub mod capped_log;
+pub mod checkpoints;
+pub mod dependencies;
 
 
+use std::sync::Arc;
 use eval::{Expr, to_value};
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
@@ -11,7 +15,9 @@ use crate::model::aliases::{Alias, Aliases};
 use crate::model::job::cmd::Cmd;
 use crate::model::job::cmd_env::CmdEnv;
 use crate::model::job::cmd_group::{AliasIter, CmdGroup};
+use crate::model::output::SummaryWriter;
 use crate::model::project::Project;
+use crate::model::run::RunState;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
@@ -28,10 +34,10 @@ impl Job {
         }
     }
 
-    pub fn exec_on_pool(&self, pool: ThreadPool, project: &Project, aliases: &Aliases) {
+    pub fn exec_on_pool(&self, pool: ThreadPool, project: &Project, aliases: &Aliases, summary: &Arc<SummaryWriter>, state: &Arc<RunState>) {
         match self {
-            Job::Exec(cmd) => cmd.exec_on_pool(pool, project.clone(), aliases.clone()),
-            Job::Batch(group) => group.exec_on_pool(pool, project, aliases)
+            Job::Exec(cmd) => cmd.exec_on_pool(pool, project.clone(), aliases.clone(), summary, state),
+            Job::Batch(group) => group.exec_on_pool(pool, project, aliases, summary, state)
         }
     }
 }
@@ -0,0 +1,83 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use crate::model::aliases::Aliases;
+use crate::model::commands::restore_str;
+
+/// Locations of the files of a project, taking precedence over the ones derived from the path of the configuration.
+/// The aliases of the configuration are restored and a relative path starts from the directory of the configuration,
+/// e.g. `layout: (log_directory: Some("/scratch/{USER}/logs"))`.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Layout {
+    /// Holds the build information and the last running configuration.
+    /// The sources, the logs and the summary are in it unless they are relocated too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_directory: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary_file: Option<String>,
+    /// Directory of the zip archives
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_directory: Option<String>,
+}
+
+impl Layout {
+    pub fn is_empty(&self) -> bool {
+        *self == Layout::default()
+    }
+
+    /// The layout with the aliases restored and the relative paths starting from the directory of `configuration`
+    pub fn resolve(&self, configuration: &Path, aliases: &Aliases) -> Layout {
+        let base = configuration.parent().unwrap_or(Path::new("."));
+        let resolve = |value: &Option<String>| value.as_ref().map(|value| {
+            let value = restore_str(value, aliases);
+            if Path::new(&value).is_absolute() || base.as_os_str().is_empty() {
+                value
+            } else {
+                base.join(value).to_string_lossy().into_owned()
+            }
+        });
+        Layout {
+            working_directory: resolve(&self.working_directory),
+            source_directory: resolve(&self.source_directory),
+            log_directory: resolve(&self.log_directory),
+            summary_file: resolve(&self.summary_file),
+            archive_directory: resolve(&self.archive_directory),
+        }
+    }
+
+    pub fn entries(&self) -> Vec<(&'static str, &String)> {
+        vec![
+            ("working_directory", &self.working_directory),
+            ("source_directory", &self.source_directory),
+            ("log_directory", &self.log_directory),
+            ("summary_file", &self.summary_file),
+            ("archive_directory", &self.archive_directory),
+        ].into_iter()
+            .filter_map(|(field, value)| value.as_ref().map(|it| (field, it)))
+            .collect()
+    }
+}
+
+/// Working directory created by `--temp-dir`. The commands run on the configuration share it (fetch, build,
+/// run...) until `remove` deletes it with its content, once the results are archived
+pub struct TemporaryDirectory {
+    pub path: PathBuf,
+}
+
+impl TemporaryDirectory {
+    /// Deletes the directory, unless the thread panics: its content may tell why
+    pub fn remove(self) {
+        if !self.path.exists() || std::thread::panicking() {
+            return;
+        }
+        match fs::remove_dir_all(&self.path) {
+            Ok(_) => info!("Temporary working directory {} deleted", self.path.display()),
+            Err(e) => warn!("Cannot delete the temporary working directory {}: {}", self.path.display(), e),
+        }
+    }
+}
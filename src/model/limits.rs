@@ -1,106 +1,191 @@
 use std::io;
+use std::process::Command;
 use std::time::Duration;
 use bytesize::{ByteSize};
 use rlimit::Resource;
 use serde::{Deserialize, Serialize};
 
+/// Resource limits (`setrlimit`) applied to every computation.
+/// They are set in the spawned process right before `exec`, so they bound the benchmarked command
+/// and the processes it starts, never whitesmith itself. Each limit is used as both the soft
+/// and the hard value. Example:
+/// ```ron
+/// limits: Some((cpu_time: Some("10min"), address_space: Some("8 GiB"), open_files: Some(1024))),
+/// ```
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Limits {
+    /// CPU time, rounded down to the second (`RLIMIT_CPU`)
     #[serde(default, with = "humantime_serde")]
     pub cpu_time: Option<Duration>,
+    /// Largest file the process may create (`RLIMIT_FSIZE`)
     #[serde(default)]
     pub file_size: Option<ByteSize>,
+    /// Data segment size (`RLIMIT_DATA`)
     #[serde(default)]
     pub data_size: Option<ByteSize>,
+    /// Stack size (`RLIMIT_STACK`)
     #[serde(default)]
     pub stack_size: Option<ByteSize>,
+    /// Core dump size (`RLIMIT_CORE`)
     #[serde(default)]
     pub core_file_size: Option<ByteSize>,
+    /// Number of processes of the user (`RLIMIT_NPROC`)
     #[serde(default)]
     pub processes: Option<u64>,
+    /// Number of open file descriptors (`RLIMIT_NOFILE`)
     #[serde(default)]
     pub open_files: Option<u64>,
+    /// Memory that may be locked in RAM (`RLIMIT_MEMLOCK`)
     #[serde(default)]
     pub locked_memory: Option<ByteSize>,
+    /// Virtual memory, the usual way to cap the memory of a computation (`RLIMIT_AS`)
     #[serde(default)]
     pub address_space: Option<ByteSize>,
+    /// Number of file locks (`RLIMIT_LOCKS`)
     #[serde(default)]
     pub file_locks: Option<u64>,
+    /// Number of queued signals (`RLIMIT_SIGPENDING`)
     #[serde(default)]
     pub pending_signals: Option<u64>,
+    /// Bytes in POSIX message queues (`RLIMIT_MSGQUEUE`)
     #[serde(default)]
     pub msgqueue_size: Option<ByteSize>,
+    /// Ceiling of the nice value (`RLIMIT_NICE`)
     #[serde(default)]
     pub nice_priority: Option<u64>,
+    /// Ceiling of the real-time priority (`RLIMIT_RTPRIO`)
     #[serde(default)]
     pub realtime_priority: Option<u64>,
+    /// CPU time without blocking call under real-time scheduling (`RLIMIT_RTTIME`)
     #[serde(default, with = "humantime_serde")]
     pub realtime_timeout: Option<Duration>,
 }
 
 impl Limits {
-    pub fn apply(&self) -> io::Result<()> {
+    /// Sets the limits on the current process. Limits that cannot be set are ignored unless `strict`.
+    /// Meant to run between `fork` and `exec`, so it must not allocate.
+    pub fn apply(&self, strict: bool) -> io::Result<()> {
         if let Some(cpu_time) = self.cpu_time {
-            Resource::CPU.set(cpu_time.as_secs(), cpu_time.as_secs())?;
+            set(Resource::CPU, cpu_time.as_secs(), strict)?;
         }
 
         if let Some(file_size) = self.file_size {
-            Resource::FSIZE.set(file_size.0, file_size.0)?;
+            set(Resource::FSIZE, file_size.0, strict)?;
         }
 
         if let Some(data_size) = self.data_size {
-            Resource::DATA.set(data_size.0, data_size.0)?;
+            set(Resource::DATA, data_size.0, strict)?;
         }
 
         if let Some(stack_size) = self.stack_size {
-            Resource::STACK.set(stack_size.0, stack_size.0)?;
+            set(Resource::STACK, stack_size.0, strict)?;
         }
 
         if let Some(core_file_size) = self.core_file_size {
-            Resource::CORE.set(core_file_size.0, core_file_size.0)?;
+            set(Resource::CORE, core_file_size.0, strict)?;
         }
 
         if let Some(processes) = self.processes {
-            Resource::NPROC.set(processes, processes)?;
+            set(Resource::NPROC, processes, strict)?;
         }
 
         if let Some(open_files) = self.open_files {
-            Resource::NOFILE.set(open_files, open_files)?;
+            set(Resource::NOFILE, open_files, strict)?;
         }
 
         if let Some(locked_memory) = self.locked_memory {
-            Resource::MEMLOCK.set(locked_memory.0, locked_memory.0)?;
+            set(Resource::MEMLOCK, locked_memory.0, strict)?;
         }
 
         if let Some(address_space) = self.address_space {
-            Resource::AS.set(address_space.0, address_space.0)?;
+            set(Resource::AS, address_space.0, strict)?;
         }
 
         if let Some(file_locks) = self.file_locks {
-            Resource::LOCKS.set(file_locks, file_locks)?;
+            set(Resource::LOCKS, file_locks, strict)?;
         }
 
         if let Some(pending_signals) = self.pending_signals {
-            Resource::SIGPENDING.set(pending_signals, pending_signals)?;
+            set(Resource::SIGPENDING, pending_signals, strict)?;
         }
 
         if let Some(msgqueue_size) = self.msgqueue_size {
-            Resource::MSGQUEUE.set(msgqueue_size.0, msgqueue_size.0)?;
+            set(Resource::MSGQUEUE, msgqueue_size.0, strict)?;
         }
 
         if let Some(nice_priority) = self.nice_priority {
-            Resource::NICE.set(nice_priority, nice_priority)?;
+            set(Resource::NICE, nice_priority, strict)?;
         }
 
         if let Some(realtime_priority) = self.realtime_priority {
-            Resource::RTPRIO.set(realtime_priority, realtime_priority)?;
+            set(Resource::RTPRIO, realtime_priority, strict)?;
         }
 
         if let Some(realtime_timeout) = self.realtime_timeout {
-            Resource::RTTIME.set(realtime_timeout.as_secs(), realtime_timeout.as_secs())?;
+            set(Resource::RTTIME, realtime_timeout.as_secs(), strict)?;
         }
 
         Ok(())
     }
+
+    /// Lists the limits that cannot be set, with the reason for each of them.
+    /// Every limit is tried on its own in a short-lived `true` process.
+    pub fn check(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (resource, value, display) in self.requested() {
+            if !resource.is_supported() {
+                errors.push(format!("{} is not supported on this platform", resource.as_name()));
+            } else if let Err(e) = try_limit(resource, value) {
+                errors.push(format!("Cannot set {} to {}: {}", resource.as_name(), display, e));
+            }
+        }
+        errors
+    }
+
+    fn requested(&self) -> Vec<(Resource, u64, String)> {
+        let duration = |it: Duration| (it.as_secs(), humantime::format_duration(it).to_string());
+        let bytes = |it: ByteSize| (it.0, it.to_string());
+        let count = |it: u64| (it, it.to_string());
+
+        vec![
+            (Resource::CPU, self.cpu_time.map(duration)),
+            (Resource::FSIZE, self.file_size.map(bytes)),
+            (Resource::DATA, self.data_size.map(bytes)),
+            (Resource::STACK, self.stack_size.map(bytes)),
+            (Resource::CORE, self.core_file_size.map(bytes)),
+            (Resource::NPROC, self.processes.map(count)),
+            (Resource::NOFILE, self.open_files.map(count)),
+            (Resource::MEMLOCK, self.locked_memory.map(bytes)),
+            (Resource::AS, self.address_space.map(bytes)),
+            (Resource::LOCKS, self.file_locks.map(count)),
+            (Resource::SIGPENDING, self.pending_signals.map(count)),
+            (Resource::MSGQUEUE, self.msgqueue_size.map(bytes)),
+            (Resource::NICE, self.nice_priority.map(count)),
+            (Resource::RTPRIO, self.realtime_priority.map(count)),
+            (Resource::RTTIME, self.realtime_timeout.map(duration)),
+        ].into_iter()
+            .filter_map(|(resource, value)| value.map(|(value, display)| (resource, value, display)))
+            .collect()
+    }
+}
+
+#[cfg(unix)]
+fn try_limit(resource: Resource, value: u64) -> io::Result<()> {
+    use std::os::unix::process::CommandExt;
+    let mut command = Command::new("true");
+    unsafe { command.pre_exec(move || resource.set(value, value)); }
+    command.status().map(|_| ())
+}
+
+#[cfg(not(unix))]
+fn try_limit(_resource: Resource, _value: u64) -> io::Result<()> {
+    Ok(())
+}
+
+fn set(resource: Resource, value: u64, strict: bool) -> io::Result<()> {
+    match resource.set(value, value) {
+        Err(e) if strict => Err(e),
+        _ => Ok(()),
+    }
 }
 
@@ -0,0 +1,117 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use log::warn;
+
+use crate::tools::hostname;
+
+pub const LOCK_FILE: &str = "whitesmith.lock";
+
+/// Lock taken on the working directory by `run`, `build` and `clean` so that two instances never
+/// write the same summary and logs. It holds `<pid>@<hostname>` and is removed when dropped.
+pub struct ProjectLock {
+    path: PathBuf,
+}
+
+// Which locks of other instances are taken over. Not a doc comment: clap would describe `build`, `run` and `clean`
+// with it in `--help`
+#[derive(Args, Debug, Default, Clone, Copy)]
+pub struct LockOptions {
+    /// Take over the lock of another instance of this host if its process is no longer running
+    #[arg(long)]
+    pub force_lock: bool,
+    /// Also take over the lock of an instance of another host, whose process cannot be checked.
+    /// Only when that instance is known to be over, e.g. its host crashed
+    #[arg(long, requires = "force_lock")]
+    pub force_foreign_lock: bool,
+}
+
+impl ProjectLock {
+    /// Takes the lock, or returns why it cannot be taken, e.g. the owner of the current one. With `force_lock` a lock
+    /// left by a process of this host that no longer exists is taken over, and with `force_foreign_lock` a lock of another host too.
+    pub fn acquire(working_directory: &str, options: &LockOptions) -> Result<ProjectLock, String> {
+        let path = lock_path(working_directory);
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let lock = ProjectLock { path };
+                    write!(file, "{}@{}", std::process::id(), hostname())
+                        .map_err(|e| format!("Cannot write the lock file {:?}: {}", lock.path, e))?;
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let owner = fs::read_to_string(&path).unwrap_or_default().trim().to_owned();
+                    let stopped = match is_alive(&owner) {
+                        Some(alive) => !alive,
+                        None => options.force_foreign_lock,
+                    };
+                    if !options.force_lock || !stopped {
+                        let hint = if is_foreign(&owner) {
+                            "It runs on another host, use --force-lock --force-foreign-lock to take over the lock once it is over"
+                        } else {
+                            "Use --force-lock to take over the lock if this process is no longer running"
+                        };
+                        return Err(format!("The project is already used by whitesmith {} (lock file {:?})\n{}", owner, path, hint));
+                    }
+                    if is_foreign(&owner) {
+                        warn!("Taking over the lock of {} which was taken on another host", owner);
+                    } else {
+                        warn!("Taking over the lock of {} which is no longer running", owner);
+                    }
+                    // Another instance may have removed it in the meantime
+                    let _ = fs::remove_file(&path);
+                }
+                Err(e) => return Err(format!("Cannot create the lock file {:?}: {}", path, e)),
+            }
+        }
+    }
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            if e.kind() != ErrorKind::NotFound {
+                warn!("Cannot remove the lock file {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
+pub fn lock_path(working_directory: &str) -> PathBuf {
+    Path::new(working_directory).join(LOCK_FILE)
+}
+
+/// Whether the owner `<pid>@<hostname>` of a lock is still running, `None` for the processes of other hosts
+/// that cannot be checked. An unreadable owner is considered as stopped.
+fn is_alive(owner: &str) -> Option<bool> {
+    let (pid, host) = match owner.split_once('@') {
+        Some(it) => it,
+        None => return Some(false),
+    };
+    if host != hostname() {
+        return None;
+    }
+    match pid.trim().parse::<u32>() {
+        Ok(pid) => Some(process_exists(pid)),
+        Err(_) => Some(false),
+    }
+}
+
+/// Whether the owner `<pid>@<hostname>` of a lock runs on another host
+fn is_foreign(owner: &str) -> bool {
+    is_alive(owner).is_none()
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_exists(_pid: u32) -> bool {
+    true
+}
@@ -0,0 +1,130 @@
+use serde_json::{Map, Value};
+use crate::model::version::Version;
+
+pub const CURRENT_VERSION: Version = Version(0, 6, 2);
+
+/// Oldest configuration version that can still be loaded
+pub const MIN_VERSION: Version = Version(0, 5, 0);
+/// First configuration version that is not compatible anymore
+pub const MAX_VERSION: Version = Version(0, 7, 0);
+
+/// Upgrades the raw document of a configuration written for a version older than `to` to the shape expected by `to`.
+/// The document is read without the types of the project, so that a step can rename, move or drop its fields.
+struct Migration {
+    to: Version,
+    apply: fn(&mut Map<String, Value>),
+}
+
+// Sorted by version, a configuration goes through every step it is older than
+const MIGRATIONS: [Migration; 1] = [
+    Migration { to: Version(0, 6, 0), apply: rename_timeout },
+];
+
+/// `timeout` was renamed `global_timeout`. The project still reads `timeout` as an alias, which the configurations
+/// of 0.6 kept using
+fn rename_timeout(document: &mut Map<String, Value>) {
+    if let Some(timeout) = document.remove("timeout") {
+        document.entry("global_timeout").or_insert(timeout);
+    }
+}
+
+pub fn is_supported(version: &Version) -> bool {
+    MIN_VERSION <= *version && *version < MAX_VERSION
+}
+
+pub fn supported_range() -> String {
+    format!(">={}, <{}", MIN_VERSION, MAX_VERSION)
+}
+
+/// Whether the document of a configuration of `version` must go through a step before it is deserialized
+pub fn changes_shape(version: &Version) -> bool {
+    MIGRATIONS.iter().any(|step| *version < step.to)
+}
+
+/// Applies the steps needed to bring the document of a configuration of `version` to the shape of `CURRENT_VERSION`.
+/// The configurations older than the last step only hold untagged enums, which the document keeps as they are.
+pub fn migrate(document: &mut Value, version: &Version) {
+    if let Value::Object(fields) = document {
+        for step in MIGRATIONS.iter().filter(|step| *version < step.to) {
+            (step.apply)(fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::time::Duration;
+    use crate::model::configuration::{parse_project, ConfigurationFormat};
+    use crate::model::job::Job;
+    use crate::model::version::Version;
+    use super::CURRENT_VERSION;
+
+    const RON_0_5: &str = r#"(
+        version: (0, 5, 3),
+        description: Some("Solvers of 2021"),
+        versioning: (url: "https://example.com/solvers.git", commit: Some("4f2a1c9")),
+        commands: (build: "make", clean: "make clean"),
+        timeout: Some("10m"),
+        iterations: 3,
+        aliases: {"SEED": 42},
+        limits: Some((cpu_time: Some("20m"), file_size: Some("1GB"))),
+        experiments: [
+            (name: "first", cmd: "./solve --seed {SEED}"),
+            (foreach: {"N": [8, 16]}, apply: (aliases: {}, cmds: [(name: "queens_{N}", cmd: "./solve queens {N}")])),
+        ],
+    )"#;
+
+    const TOML_0_5: &str = r#"
+        version = [0, 5, 0]
+        timeout = "1h 30m"
+
+        [versioning]
+        url = "https://example.com/solvers.git"
+
+        [commands]
+        build = "make"
+
+        [[experiments]]
+        name = "first"
+        cmd = "./solve"
+    "#;
+
+    #[test]
+    fn an_older_configuration_is_migrated_to_the_current_shape() {
+        let (project, migrated_from) = parse_project(Path::new("solvers.ron"), RON_0_5, ConfigurationFormat::Ron);
+
+        assert_eq!(migrated_from, Some(Version(0, 5, 3)));
+        assert_eq!(project.version, CURRENT_VERSION);
+        assert_eq!(project.global_timeout, Some(Duration::from_secs(600)));
+        assert_eq!(project.description.as_deref(), Some("Solvers of 2021"));
+        assert_eq!(project.versioning.commit.as_deref(), Some("4f2a1c9"));
+        assert_eq!(project.iterations, 3);
+        assert_eq!(project.limits.as_ref().and_then(|it| it.cpu_time), Some(Duration::from_secs(1200)));
+        assert_eq!(project.experiments.len(), 2);
+        assert!(matches!(&project.experiments[1], Job::Batch(_)));
+
+        let (project, migrated_from) = parse_project(Path::new("solvers.toml"), TOML_0_5, ConfigurationFormat::Toml);
+        assert_eq!(migrated_from, Some(Version(0, 5, 0)));
+        assert_eq!(project.global_timeout, Some(Duration::from_secs(5400)));
+    }
+
+    #[test]
+    fn a_current_configuration_is_read_as_is() {
+        let configuration = RON_0_5.replace("version: (0, 5, 3)", "version: (0, 6, 2)")
+            .replace("timeout:", "global_timeout:");
+        let (project, migrated_from) = parse_project(Path::new("solvers.ron"), &configuration, ConfigurationFormat::Ron);
+
+        assert_eq!(migrated_from, None);
+        assert_eq!(project.global_timeout, Some(Duration::from_secs(600)));
+    }
+
+    #[test]
+    fn a_current_configuration_may_still_use_timeout() {
+        let configuration = RON_0_5.replace("version: (0, 5, 3)", "version: (0, 6, 2)");
+        let (project, migrated_from) = parse_project(Path::new("solvers.ron"), &configuration, ConfigurationFormat::Ron);
+
+        assert_eq!(migrated_from, None);
+        assert_eq!(project.global_timeout, Some(Duration::from_secs(600)));
+    }
+}
@@ -2,16 +2,25 @@ use std::path::{Path, PathBuf};
 use crate::model::project::Project;
 use std::ffi::OsStr;
 use crate::model::versioning::Versioning;
+use crate::model::layout::Layout;
 
 pub mod project;
 pub mod versioning;
 pub mod commands;
+pub mod children;
 pub mod computation_result;
 pub mod limits;
 pub mod aliases;
 pub mod output;
 pub mod job;
 pub mod version;
+pub mod migration;
+pub mod build;
+pub mod lock;
+pub mod shard;
+pub mod layout;
+pub mod configuration;
+pub mod run;
 
 // Utils
 fn parent_of(path: &Path) -> String {
@@ -19,7 +28,7 @@ fn parent_of(path: &Path) -> String {
         .and_then(Path::to_str)
         .unwrap_or("/");
 
-    if parent == "" {
+    if parent.is_empty() {
         String::from(".")
     } else {
         parent.to_owned()
@@ -33,51 +42,85 @@ fn file_name(path: &Path) -> String {
         .to_owned()
 }
 
-pub fn working_directory(path: &PathBuf, versioning: &Versioning) -> String {
-    let commit_hash = versioning.commit.as_ref()
+fn commit_suffix(versioning: &Versioning) -> String {
+    versioning.commit.as_ref()
         .map(|it| String::from("-") + &it[..6])
-        .unwrap_or(String::new());
-    format!("{}/{}{}.d", parent_of(path), file_name(path), commit_hash)
+        .unwrap_or_default()
 }
 
-pub fn source_directory(path: &PathBuf, versioning: &Versioning) -> String {
-    let commit_hash = versioning.commit.as_ref()
-        .map(|it| String::from("-") + &it[..6])
-        .unwrap_or(String::new());
-    format!("{}/{}{}.d/src", parent_of(path), file_name(path), commit_hash)
+pub fn working_directory(path: &Path, versioning: &Versioning, layout: &Layout) -> String {
+    layout.working_directory.clone().unwrap_or_else(|| {
+        format!("{}/{}{}.d", parent_of(path), file_name(path), commit_suffix(versioning))
+    })
 }
 
-pub fn log_directory(path: &PathBuf, versioning: &Versioning) -> String {
-    let commit_hash = versioning.commit.as_ref()
-        .map(|it| String::from("-") + &it[..6])
-        .unwrap_or(String::new());
-    format!("{}/{}{}.d/logs", parent_of(path), file_name(path), commit_hash)
+/// Working directory under `root`, named after the configuration and its version.
+/// The checksum of the path of the configuration tells apart the configurations with the same name.
+pub fn temporary_working_directory(root: &Path, path: &Path, p: &Project) -> String {
+    let absolute = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    let checksum = crc32fast::hash(absolute.to_string_lossy().as_bytes());
+    root.join("whitesmith")
+        .join(format!("{}-{}{}-{:08x}", file_name(path), p.version, commit_suffix(&p.versioning), checksum))
+        .to_string_lossy()
+        .into_owned()
+}
+
+pub fn source_directory(path: &Path, versioning: &Versioning, layout: &Layout) -> String {
+    layout.source_directory.clone().unwrap_or_else(|| {
+        format!("{}/src", working_directory(path, versioning, layout))
+    })
 }
 
-pub fn summary_file(path: &PathBuf, versioning: &Versioning, is_zip_archive: bool) -> String {
+pub fn log_directory(path: &Path, versioning: &Versioning, layout: &Layout) -> String {
+    layout.log_directory.clone().unwrap_or_else(|| {
+        format!("{}/logs", working_directory(path, versioning, layout))
+    })
+}
+
+pub fn summary_file(path: &Path, versioning: &Versioning, layout: &Layout, is_zip_archive: bool) -> String {
     if is_zip_archive {
         let mut name = file_name(path);
 
-        if let Some(pos) = name.find('#') {
-            name = String::from(&name[..pos]) + ".csv"
+        // The archive name is `<name>[#<commit>]@<time>.zip`
+        if let Some(pos) = name.find(['#', '@']) {
+            name.truncate(pos);
         }
 
-        name
+        name + ".csv"
     } else {
-        let commit_hash = versioning.commit.as_ref()
-            .map(|it| String::from("-") + &it[..6])
-            .unwrap_or(String::new());
-        format!("{0}/{1}{2}.d/{1}.csv", parent_of(path), file_name(path), commit_hash)
+        layout.summary_file.clone().unwrap_or_else(|| {
+            format!("{}/{}.csv", working_directory(path, versioning, layout), file_name(path))
+        })
     }
 }
 
-pub fn zip_file(path: &PathBuf, p: &Project) -> String {
+fn archive_directory(path: &Path, p: &Project) -> String {
+    p.layout.archive_directory.clone().unwrap_or_else(|| parent_of(path))
+}
+
+pub fn zip_file(path: &Path, p: &Project) -> String {
     let time = chrono::Local::now()
         .format("%Y-%m-%dT%H-%M")
         .to_string();
     if let Some(commit) = &p.versioning.commit {
-        format!("{}/{}#{}@{}.zip", parent_of(path), file_name(path), &commit[0..8], time)
+        format!("{}/{}#{}@{}.zip", archive_directory(path, p), file_name(path), &commit[0..8], time)
     } else {
-        format!("{}/{}@{}.zip", parent_of(path), file_name(path), time)
+        format!("{}/{}@{}.zip", archive_directory(path, p), file_name(path), time)
+    }
+}
+
+/// Path of the archive saved before cleaning, e.g. `name.backup.2024-06-01T12-00-00.zip`.
+/// The timestamp keeps the backups of successive cleanings.
+pub fn backup_file(path: &Path, p: &Project) -> PathBuf {
+    let time = chrono::Local::now()
+        .format("%Y-%m-%dT%H-%M-%S")
+        .to_string();
+    let mut stem = path.file_stem()
+        .expect("The project path has no file name")
+        .to_os_string();
+    if let Some(commit) = &p.versioning.commit {
+        stem.push(format!("#{}", &commit[0..8]));
     }
+    stem.push(format!(".backup.{}.zip", time));
+    Path::new(&archive_directory(path, p)).join(stem)
 }
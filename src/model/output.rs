@@ -1,16 +1,281 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use std::collections::BTreeMap;
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use csv::QuoteStyle;
 use serde::{Serialize, Serializer};
+use log::{info, warn};
+use crate::model::computation_result::{ComputationResult, RunReport};
+use crate::model::project::MeasureMode;
+
+/// Columns of every summary. The new ones go at the end so that the columns of an older summary keep their position
+pub const HEADERS: [&str; 13] = ["name", "status", "time", "iteration", "started_at", "hostname", "log", "partial_result", "user_time", "system_time", "threads", "cores", "measure_mode"];
+
+/// Appends rows to the summary file as soon as they are produced.
+/// Every row is formatted beforehand and written with a single `write_all` while holding the lock,
+/// then synced to disk, so that a crash only loses the computations in progress.
+#[derive(Debug)]
+pub struct SummaryWriter {
+    file: Mutex<File>,
+    /// Names of the metric columns that follow `HEADERS`
+    metrics: Vec<String>,
+    /// Number of rows written by status, with the total time of their computations
+    results: Mutex<BTreeMap<String, (u64, Duration)>>,
+    report: Mutex<RunReport>,
+}
+
+impl SummaryWriter {
+    /// Opens the summary to append rows to it. A summary written with fewer columns, e.g. by an older version
+    /// or before a metric was added, is migrated to the current ones. It is refused when it has columns
+    /// that the rows would no longer fill.
+    pub fn open(path: &Path, metrics: Vec<String>) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+
+        drop_partial_line(&mut file)?;
+
+        let header = HEADERS.iter().copied()
+            .chain(metrics.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        if file.metadata()?.len() == 0 {
+            file.write_all(&format_record(&header)?)?;
+        } else if first_line(&mut file)? != header.join("\t") {
+            migrate_columns(path, &header)?;
+            file = OpenOptions::new().read(true).append(true).open(path)?;
+        }
+
+        Ok(SummaryWriter { file: Mutex::new(file), metrics, results: Mutex::new(BTreeMap::new()), report: Mutex::new(RunReport::default()) })
+    }
+
+    pub fn metrics(&self) -> &[String] {
+        &self.metrics
+    }
+
+    pub fn write_line(&self, line: &OutputLine) -> io::Result<()> {
+        let buffer = format_record(line)?;
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.write_all(&buffer)?;
+        file.sync_data()?;
+        drop(file);
+
+        let mut results = self.results.lock().unwrap_or_else(PoisonError::into_inner);
+        let (count, time) = results.entry(line.status.clone()).or_default();
+        *count += 1;
+        *time += line.time.duration();
+        Ok(())
+    }
+
+    /// Rows written since the summary was opened, by status
+    pub fn results(&self) -> BTreeMap<String, (u64, Duration)> {
+        self.results.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    /// Adds the result of a computation to the report of the run, see `report`
+    pub fn record(&self, name: String, result: ComputationResult) {
+        self.report.lock().unwrap_or_else(PoisonError::into_inner).results.push((name, result));
+    }
+
+    /// Results recorded since the summary was opened
+    pub fn report(&self) -> RunReport {
+        self.report.lock().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+/// Formats a row of the summary. The fields are never quoted: every reader splits the lines on tabs,
+/// the cells that may hold them are sanitized instead (see `sanitize`).
+fn format_record<T: Serialize>(record: T) -> io::Result<Vec<u8>> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .delimiter(b'\t')
+        .quote_style(QuoteStyle::Never)
+        .from_writer(Vec::new());
+    csv_writer.serialize(record)?;
+    csv_writer.into_inner()
+        .map_err(|e| e.into_error())
+}
+
+/// Replaces the tabs and line breaks of a cell with spaces, so that a row is always a single line of
+/// tab-separated cells
+fn sanitize(value: &str) -> Cow<'_, str> {
+    if value.contains(['\t', '\n', '\r']) {
+        Cow::Owned(value.replace(['\t', '\n', '\r'], " "))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn sanitized<S: Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&sanitize(value))
+}
+
+fn all_sanitized<S: Serializer>(values: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(|it| sanitize(it)))
+}
+
+fn first_line(file: &mut File) -> io::Result<String> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line)?;
+    Ok(line.trim_end().to_owned())
+}
+
+/// Rewrites the summary at `path` with the columns of `header`, the missing cells being left empty
+fn migrate_columns(path: &Path, header: &[&str]) -> io::Result<()> {
+    let content = fs::read_to_string(path)?;
+    let mut rows = previous_rows(&content)?.into_iter();
+    let previous = rows.next().unwrap_or_default();
+    let dropped = previous.iter()
+        .filter(|column| !header.contains(&column.as_str()))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if !dropped.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!(
+            "its columns {} are no longer produced by the configuration, archive then clean the project to start a new summary",
+            dropped.join(", "),
+        )));
+    }
+
+    let positions = header.iter()
+        .map(|column| previous.iter().position(|it| it == column))
+        .collect::<Vec<_>>();
+    let mut migrated = format_record(header)?;
+    for cells in rows {
+        let row = positions.iter()
+            .map(|position| position.and_then(|it| cells.get(it)).map(String::as_str).unwrap_or_default())
+            .collect::<Vec<_>>();
+        migrated.extend(format_record(row)?);
+    }
+
+    // Renamed over the summary so that it is never left half migrated
+    let migrating = path.with_extension("migrating");
+    fs::write(&migrating, migrated)?;
+    File::open(&migrating)?.sync_all()?;
+    fs::rename(&migrating, path)?;
+    info!("The summary file {} has been migrated to the columns {}", path.display(), header.join(", "));
+    Ok(())
+}
+
+/// Header and rows of a summary. The summaries of the earlier versions were comma-separated with quoted cells,
+/// they are recognized by a header without tabs and read with the csv reader
+fn previous_rows(content: &str) -> io::Result<Vec<Vec<String>>> {
+    let header = content.lines().next().unwrap_or_default();
+    if header.contains(',') && !header.contains('\t') {
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(content.as_bytes())
+            .records()
+            .map(|record| Ok(record?.iter().map(|cell| sanitize(cell).into_owned()).collect()))
+            .collect()
+    } else {
+        Ok(content.lines().map(|line| line.split('\t').map(str::to_owned).collect()).collect())
+    }
+}
+
+/// Truncates the file after its last line feed, removing a row that was only partially written
+/// (e.g. when the process was killed in the middle of a write).
+fn drop_partial_line(file: &mut File) -> io::Result<()> {
+    let len = file.metadata()?.len();
+    let mut end = len;
+    let mut buffer = [0u8; 4096];
+    while end > 0 {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(pos) = chunk.iter().rposition(|&it| it == b'\n') {
+            let valid_len = start + pos as u64 + 1;
+            if valid_len != len {
+                warn!("Dropping the truncated last row of the summary file");
+                file.set_len(valid_len)?;
+            }
+            return Ok(());
+        }
+        end = start;
+    }
+    file.set_len(0)
+}
 
 
 #[derive(Serialize, Debug)]
 pub struct OutputLine {
+    #[serde(serialize_with = "sanitized")]
     pub name: String,
     pub status: String,
-    pub time: Seconds,
+    pub time: Time,
     pub iterations: Iterations,
+    pub started_at: String,
+    pub hostname: String,
+    /// Stderr of the run, relative to the log directory
+    #[serde(serialize_with = "sanitized")]
+    pub log: String,
+    /// Best-so-far result read from the logs of a run that timed out, see `Project::partial_result`
+    #[serde(serialize_with = "sanitized")]
+    pub partial_result: String,
+    /// CPU time of the command, empty when it did not run or on a platform without `wait4`
+    pub user_time: Option<Time>,
+    pub system_time: Option<Time>,
+    /// Number of computations run in parallel
+    pub threads: usize,
+    /// Number of logical CPUs of the machine
+    pub cores: usize,
+    pub measure_mode: MeasureMode,
+    /// Values of the metric columns of the summary, empty when the regex did not match
+    #[serde(serialize_with = "all_sanitized")]
+    pub metrics: Vec<String>,
 }
 
-#[derive(Serialize, Debug)]
-pub struct Seconds(pub f64);
+#[derive(Debug)]
+pub enum Time {
+    Seconds(f64),
+    Human(Duration),
+}
+
+impl Time {
+    pub fn new(duration: Duration, raw_seconds: bool) -> Self {
+        if raw_seconds {
+            Time::Seconds(duration.as_secs_f64())
+        } else {
+            // Sub-millisecond precision is noise for a benchmark summary
+            Time::Human(Duration::from_millis(duration.as_millis() as u64))
+        }
+    }
+}
+
+impl Time {
+    pub fn duration(&self) -> Duration {
+        match self {
+            Time::Seconds(seconds) => Duration::from_secs_f64(*seconds),
+            Time::Human(duration) => *duration,
+        }
+    }
+}
+
+impl Display for Time {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Time::Seconds(seconds) => write!(f, "{}", seconds),
+            Time::Human(duration) => write!(f, "{}", humantime::format_duration(*duration)),
+        }
+    }
+}
+
+impl Serialize for Time {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        match self {
+            Time::Seconds(seconds) => serializer.serialize_f64(*seconds),
+            Time::Human(duration) => serializer.serialize_str(&humantime::format_duration(*duration).to_string()),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Iterations(pub u32, pub u32);
@@ -19,4 +284,145 @@ impl Serialize for Iterations {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
         serializer.serialize_str(&format!("{}/{}", self.0, self.1))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::time::Duration;
+    use crate::model::project::MeasureMode;
+    use super::{Iterations, OutputLine, SummaryWriter, Time, HEADERS};
+
+    fn output_line(name: &str) -> OutputLine {
+        OutputLine {
+            name: name.to_owned(),
+            status: String::from("Ok"),
+            time: Time::new(Duration::from_millis(1500), false),
+            user_time: Some(Time::new(Duration::from_millis(1200), false)),
+            system_time: None,
+            iterations: Iterations(1, 1),
+            started_at: String::from("2024-06-01T12:00:00+02:00"),
+            hostname: String::from("localhost"),
+            threads: 4,
+            cores: 8,
+            measure_mode: MeasureMode::Throughput,
+            log: format!("{0}/{0}_1.err", name),
+            partial_result: String::new(),
+            metrics: vec![String::from("42")],
+        }
+    }
+
+    #[test]
+    fn interrupted_write_leaves_a_parsable_summary() {
+        let path = std::env::temp_dir().join(format!("whitesmith-summary-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let writer = SummaryWriter::open(&path, vec![String::from("objective")]).unwrap();
+        writer.write_line(&output_line("first")).unwrap();
+        writer.write_line(&output_line("second")).unwrap();
+        drop(writer);
+
+        // Simulate a process killed in the middle of writing a row
+        fs::OpenOptions::new().append(true).open(&path).unwrap()
+            .write_all(b"third\tO").unwrap();
+
+        let writer = SummaryWriter::open(&path, vec![String::from("objective")]).unwrap();
+        writer.write_line(&output_line("fourth")).unwrap();
+        drop(writer);
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let rows = content.lines()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows[0][..HEADERS.len()], HEADERS);
+        assert_eq!(rows[0][HEADERS.len()..], ["objective"]);
+        assert_eq!(rows.iter().skip(1).map(|row| row[0]).collect::<Vec<_>>(), ["first", "second", "fourth"]);
+        assert!(rows.iter().all(|row| row.len() == HEADERS.len() + 1));
+        assert_eq!(rows[1], ["first", "Ok", "1s 500ms", "1/1", "2024-06-01T12:00:00+02:00", "localhost", "first/first_1.err", "", "1s 200ms", "", "4", "8", "throughput", "42"]);
+    }
+
+    #[test]
+    fn cells_never_break_a_row() {
+        let path = std::env::temp_dir().join(format!("whitesmith-sanitized-{}.csv", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut line = output_line("first");
+        line.partial_result = String::from("best:\t\"12\"\r\n");
+        line.metrics = vec![String::from("4\t2")];
+        let writer = SummaryWriter::open(&path, vec![String::from("objective")]).unwrap();
+        writer.write_line(&line).unwrap();
+        drop(writer);
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let rows = content.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 2);
+        let cells = rows[1].split('\t').collect::<Vec<_>>();
+        assert_eq!(cells[7], "best: \"12\"  ");
+        assert_eq!(cells[HEADERS.len()], "4 2");
+    }
+
+    #[test]
+    fn an_older_summary_is_migrated_to_the_current_columns() {
+        let path = std::env::temp_dir().join(format!("whitesmith-migrated-{}.csv", std::process::id()));
+        fs::write(&path, "name\tstatus\ttime\titeration\tstarted_at\thostname\tlog\tpartial_result\n\
+            old\tOk\t2s\t1/1\t2024-01-01T12:00:00+02:00\tlocalhost\told/old_1.err\t\n").unwrap();
+
+        let writer = SummaryWriter::open(&path, vec![String::from("objective")]).unwrap();
+        writer.write_line(&output_line("new")).unwrap();
+        drop(writer);
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let rows = content.lines()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0][..HEADERS.len()], HEADERS);
+        assert_eq!(rows[1], ["old", "Ok", "2s", "1/1", "2024-01-01T12:00:00+02:00", "localhost", "old/old_1.err", "", "", "", "", "", "", ""]);
+        assert_eq!(rows[2][0], "new");
+        assert!(rows.iter().all(|row| row.len() == HEADERS.len() + 1));
+    }
+
+    #[test]
+    fn a_comma_separated_summary_is_migrated_to_the_current_columns() {
+        let path = std::env::temp_dir().join(format!("whitesmith-comma-{}.csv", std::process::id()));
+        fs::write(&path, "name,status,time,iteration\nold,Ok,2.5,1/1\n\"old, quoted\",Error,0.1,1/3\n").unwrap();
+
+        let writer = SummaryWriter::open(&path, vec![String::from("objective")]).unwrap();
+        writer.write_line(&output_line("new")).unwrap();
+        drop(writer);
+
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let rows = content.lines()
+            .map(|line| line.split('\t').collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0][..HEADERS.len()], HEADERS);
+        assert_eq!(rows[1][..4], ["old", "Ok", "2.5", "1/1"]);
+        assert_eq!(rows[2][..4], ["old, quoted", "Error", "0.1", "1/3"]);
+        assert_eq!(rows[3][0], "new");
+        assert!(rows.iter().all(|row| row.len() == HEADERS.len() + 1));
+    }
+
+    #[test]
+    fn a_summary_with_columns_no_longer_produced_is_refused() {
+        let path = std::env::temp_dir().join(format!("whitesmith-refused-{}.csv", std::process::id()));
+        let previous = "name\tstatus\tobjective\nold\tOk\t12\n";
+        fs::write(&path, previous).unwrap();
+
+        let error = SummaryWriter::open(&path, Vec::new()).unwrap_err();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(error.to_string().contains("objective"), "{}", error);
+        assert_eq!(content, previous);
+    }
+}
@@ -1,30 +1,64 @@
 use std::{io, fs};
-use std::path::{Path};
-use crate::model::versioning::Versioning;
+use std::path::{Path, PathBuf};
+use crate::model::versioning::{fetch, fetch_cached, is_clone_of, update, Versioning};
 use crate::model::job::{Job};
-use crate::model::commands::{Commands};
-use std::time::{Duration};
-use std::fs::{File};
+use crate::model::commands::{Commands, restore_str, unresolved_aliases};
+use std::time::{Duration, Instant};
+use chrono::Local;
+use crate::model::build::{fingerprint, BuildInfo};
 use std::io::{Write};
+use clap::ValueEnum;
+use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use serde::{Serialize, Deserialize};
-use std::process::{Command, Stdio};
 use colored::Colorize;
 use threadpool::ThreadPool;
+use log::{debug, error, info, warn, LevelFilter};
 use crate::model::aliases::Aliases;
-use crate::model::job::cmd_env::CmdEnv;
+use crate::model::layout::Layout;
+use crate::model::job::cmd_env::{CmdEnv, PrefixStyle, Status};
+use crate::model::job::dependencies::DependencyGraph;
 use crate::model::limits::Limits;
+use bytesize::ByteSize;
+use crate::model::computation_result::RunReport;
+use crate::model::run::RunState;
+use crate::model::output::{SummaryWriter, HEADERS};
 use crate::model::version::Version;
+use crate::tools::notes::Theme;
+use crate::tools::notify::NotifyOn;
+use crate::tools::sanitize_file_name;
+use regex::Regex;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProjectVersionOnly {
     pub version: Version,
 }
 
+/// Content of a file listed in `include`: computations and aliases shared between configurations.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Include {
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+    #[serde(default)]
+    pub aliases: Aliases,
+    #[serde(default)]
+    pub experiments: Vec<Job>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Project {
     pub version: Version,
+    /// Version of whitesmith that archived the results, only set in the `configuration.ron` of a zip archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub whitesmith_version: Option<String>,
     #[serde(default)]
     pub description: Option<String>,
+    /// Theme used by `show notes`, `--theme` takes precedence
+    #[serde(default)]
+    pub notes_theme: Theme,
+    /// Overrides of the locations of the working directory, the sources, the logs, the summary and the archives
+    #[serde(default, skip_serializing_if = "Layout::is_empty")]
+    pub layout: Layout,
     #[serde(default, skip_serializing)]
     pub working_directory: String,
     #[serde(default, skip_serializing)]
@@ -34,6 +68,10 @@ pub struct Project {
     #[serde(default, skip_serializing)]
     pub summary_file: String,
     pub versioning: Versioning,
+    /// Files whose computations and aliases are merged into the project, relative to the configuration file.
+    /// The aliases of the including file take precedence, then the ones of the last included file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
     pub commands: Commands,
     pub experiments: Vec<Job>,
     #[serde(default, with = "humantime_serde", alias = "timeout")]
@@ -44,10 +82,82 @@ pub struct Project {
     pub aliases: Aliases,
     #[serde(default)]
     pub debug: bool,
+    /// How much is printed about the commands being run, set by `--quiet` and `--debug`
+    #[serde(skip)]
+    pub verbosity: Verbosity,
+    #[serde(default)]
+    pub raw_seconds: bool,
     #[serde(default)]
     pub zip_with: Vec<String>,
+    /// Glob patterns of the files that are never added to the zip archives (e.g. `"core.*"`)
+    #[serde(default)]
+    pub exclude: Vec<String>,
     #[serde(default)]
     pub limits: Option<Limits>,
+    #[serde(default)]
+    pub strict_limits: bool,
+    /// Size above which the stdout and stderr logs of a computation only keep their tail.
+    /// The computation is killed if it writes ten times more.
+    #[serde(default)]
+    pub max_log_bytes: Option<ByteSize>,
+    /// Regex looked for in the logs of a computation that timed out, e.g. `"best: (\\d+)"`. Its last match
+    /// (the first group if any) is written in the `partial_result` column of the summary. Aliases are restored.
+    #[serde(default)]
+    pub partial_result: Option<String>,
+    /// Echo the output of the computations to the terminal, set by `run --tee`
+    #[serde(skip)]
+    pub tee: bool,
+    /// Tag the lines printed for a computation with its name, set by `run --prefix`
+    #[serde(skip)]
+    pub prefix: Option<PrefixStyle>,
+    /// Webhook (e.g. Slack or Discord) receiving a JSON summary when a run ends or is interrupted
+    #[serde(default)]
+    pub notify_url: Option<String>,
+    /// Runs after which `notify_url` is called, set by `run --notify-on`
+    #[serde(skip)]
+    pub notify_on: NotifyOn,
+    /// Names of the computations to run, all of them when `None`, set by `run --shard`
+    #[serde(skip)]
+    pub selection: Option<HashSet<String>>,
+    #[serde(default)]
+    pub nb_threads: Option<usize>,
+    #[serde(default)]
+    pub pin_cpus: bool,
+    /// Whether the computations run one at a time for clean timings or in parallel, see `run --measure-mode`
+    #[serde(default)]
+    pub measure_mode: MeasureMode,
+}
+
+/// How the computations share the machine. Each row of the summary records the mode that produced it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MeasureMode {
+    /// One computation at a time, whatever `nb_threads`, so that they do not contend for the CPUs and the memory
+    Isolated,
+    /// `nb_threads` computations in parallel, for the shortest run
+    #[default]
+    Throughput,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the results and the errors
+    Quiet,
+    /// Also the commands being run
+    #[default]
+    Normal,
+    Debug,
+}
+
+impl Verbosity {
+    /// Default level of the diagnostics, `WHITESMITH_LOG` (e.g. `warn` or `whitesmith::model=debug`) takes precedence
+    pub fn level_filter(&self) -> LevelFilter {
+        match self {
+            Verbosity::Quiet => LevelFilter::Warn,
+            Verbosity::Normal => LevelFilter::Info,
+            Verbosity::Debug => LevelFilter::Debug,
+        }
+    }
 }
 
 fn default_nb_iterations() -> u32 {
@@ -60,6 +170,10 @@ impl Project {
             fs::remove_file(&self.summary_file)
                 .expect("Cannot remove summary_file");
         }
+        if self.fingerprint_file().exists() {
+            fs::remove_file(self.fingerprint_file())
+                .expect("Cannot remove the fingerprint of the summary file");
+        }
         if Path::new(&self.log_directory).exists() {
             fs::remove_dir_all(&self.log_directory)
                 .expect("Fail to remove logs directory");
@@ -68,26 +182,130 @@ impl Project {
         self.init();
     }
 
-    pub fn write_headers(&self, file: &mut File) -> io::Result<()> {
-        let mut csv_writer = csv::Writer::from_writer(file);
-        csv_writer.write_record(&["name", "status", "time", "iteration"])?;
-        Ok(())
+    /// Removes the logs and summary rows of the computations matching `only` and, when any of them is set,
+    /// the ones that failed (`with_failure`) or timed out (`with_timeout`). The other results are kept.
+    pub fn clean_selected(&self, only: &Option<Vec<String>>, with_failure: bool, with_timeout: bool) {
+        let mut cleaned = HashSet::new();
+        for cmd_env in &self.cmd_envs() {
+            let selected = cmd_env.match_any(only) && (
+                (!with_failure && !with_timeout)
+                    || (with_failure && cmd_env.has_err_tag())
+                    || (with_timeout && cmd_env.has_timeout_tag())
+            );
+            if selected {
+                info!("Cleaning {}", cmd_env.name());
+                fs::remove_dir_all(cmd_env.log_dir())
+                    .unwrap_or_else(|_| panic!("Cannot remove the log directory for {}", cmd_env.name()));
+                cleaned.insert(cmd_env.name());
+            }
+        }
+
+        if !cleaned.is_empty() && Path::new(&self.summary_file).exists() {
+            self.remove_summary_rows(&cleaned)
+                .expect("Cannot remove the cleaned computations from the summary file");
+        }
+    }
+
+    fn remove_summary_rows(&self, names: &HashSet<String>) -> io::Result<()> {
+        let content = fs::read_to_string(&self.summary_file)?;
+        let mut kept = String::with_capacity(content.len());
+        for (i, line) in content.lines().enumerate() {
+            let name = line.split('\t').next().unwrap_or("");
+            if i == 0 || !names.contains(name) {
+                kept.push_str(line);
+                kept.push('\n');
+            }
+        }
+
+        let tmp_file = format!("{}.tmp", &self.summary_file);
+        fs::write(&tmp_file, kept)?;
+        fs::rename(&tmp_file, &self.summary_file)
     }
 
-    pub fn run(&self, pool: ThreadPool) {
-        let summary_tsv = fs::OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(&self.summary_file);
+    /// Checksum of the computations of the configuration: their names, commands and iterations
+    pub fn fingerprint(&self) -> String {
+        let mut computations = self.cmd_envs().iter()
+            .map(|cmd_env| format!("{}\t{}", cmd_env.name(), cmd_env.cmd.cmd))
+            .collect::<Vec<_>>();
+        computations.sort();
+        computations.push(self.iterations.to_string());
+        format!("{:08x}", crc32fast::hash(computations.join("\n").as_bytes()))
+    }
 
-        if let Ok(mut summary_tsv) = summary_tsv {
-            self.write_headers(&mut summary_tsv)
-                .expect("Failed to wrap the headers of the summary file");
+    fn fingerprint_file(&self) -> PathBuf {
+        PathBuf::from(format!("{}.fingerprint", self.summary_file))
+    }
+
+    /// Warns when the rows of the summary file were produced by another version of the configuration.
+    /// With `record`, a summary file without rows gets the fingerprint of the current configuration.
+    pub fn check_fingerprint(&self, record: bool) {
+        let has_rows = fs::read_to_string(&self.summary_file)
+            .map(|it| it.lines().nth(1).is_some())
+            .unwrap_or(false);
+        let fingerprint = self.fingerprint();
+        match fs::read_to_string(self.fingerprint_file()) {
+            Ok(previous) if has_rows && previous.trim() != fingerprint => warn!(
+                "The summary file {} was produced by another version of the configuration (computations added, removed or changed), \
+                its results may be inconsistent. Clean the project to start again.",
+                self.summary_file
+            ),
+            Ok(_) => {}
+            Err(_) if has_rows => debug!("The summary file {} has no fingerprint", self.summary_file),
+            Err(_) => {}
+        }
+        if record && !has_rows {
+            fs::write(self.fingerprint_file(), fingerprint + "\n")
+                .expect("Cannot write the fingerprint of the summary file");
         }
+    }
 
-        for experiment in &self.experiments {
-            experiment.exec_on_pool(pool.clone(), self, &self.aliases);
+    /// Runs the computations on `pool` and waits for them. Their results are written in `summary` and returned.
+    pub fn run(&self, pool: ThreadPool, summary: Arc<SummaryWriter>, state: &Arc<RunState>) -> RunReport {
+        let cmd_envs = self.cmd_envs();
+        if cmd_envs.iter().any(|it| !it.cmd.depends_on.is_empty()) {
+            DependencyGraph::new(cmd_envs).run(&pool, &summary, state);
+        } else {
+            for experiment in &self.experiments {
+                match experiment {
+                    // Run by a worker like the computations of a batch, never by the calling thread, which `pin_cpus`
+                    // would pin for good
+                    Job::Exec(cmd) => {
+                        let (cmd, project, aliases) = (cmd.clone(), self.clone(), self.aliases.clone());
+                        let (captured_pool, summary, state) = (pool.clone(), summary.clone(), state.clone());
+                        pool.execute(move || cmd.exec_on_pool(captured_pool, project, aliases, &summary, &state));
+                    }
+                    Job::Batch(_) => experiment.exec_on_pool(pool.clone(), self, &self.aliases, &summary, state),
+                }
+            }
+        }
+        pool.join();
+        summary.report()
+    }
+
+    /// Computations sharing a log directory, i.e. whose names are the same once the aliases are restored
+    /// and the name made safe for the file system, e.g. `2 computations are named a` or `a b and a_b share the logs a_b`
+    pub fn duplicate_names(&self) -> Vec<String> {
+        let mut names = BTreeMap::<_, Vec<String>>::new();
+        for cmd_env in &self.cmd_envs() {
+            let name = cmd_env.name();
+            names.entry(sanitize_file_name(&name)).or_default().push(name);
         }
+        names.into_iter()
+            .filter(|(_, names)| names.len() > 1)
+            .map(|(directory, names)| {
+                let distinct = names.iter().map(String::as_str).collect::<BTreeSet<_>>();
+                if distinct.len() == 1 {
+                    format!("{} computations are named {}", names.len(), names[0])
+                } else {
+                    format!("{} share the logs {}", distinct.into_iter().collect::<Vec<_>>().join(" and "), directory)
+                }
+            })
+            .collect()
+    }
+
+    /// Dependencies on unknown computations and cycles of dependencies
+    pub fn dependency_problems(&self) -> Vec<String> {
+        DependencyGraph::new(self.cmd_envs()).problems()
     }
 
     pub fn requires_overrides(&self) -> bool {
@@ -95,7 +313,7 @@ impl Project {
         for (key, value) in self.aliases.iter() {
             let value = value.to_string();
             if let Some('!') = value.chars().next() {
-                eprintln!("The key {0} must be overridden by '{1}'. Use (--override {0}:'{1}').", key, &value[1..]);
+                warn!("The key {0} must be overridden by '{1}'. Use (--override {0}:'{1}').", key, &value[1..]);
                 requires_overrides = true;
             }
         }
@@ -103,6 +321,139 @@ impl Project {
         requires_overrides
     }
 
+    /// Lists the problems of the configuration: aliases that still need to be overridden or that are
+    /// not defined, and computations without a name or a command.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut report = |problem: String| {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        };
+
+        for (key, value) in self.aliases.iter() {
+            let value = value.to_string();
+            if let Some(hint) = value.strip_prefix('!') {
+                report(format!("The alias {} must be overridden by '{}'", key, hint));
+            }
+        }
+
+        let build_cwd = self.commands.cwd.clone().unwrap_or_default();
+        let mut commands = self.commands.build.steps().into_iter()
+            .map(|step| ("build", step))
+            .collect::<Vec<_>>();
+        commands.push(("clean", &self.commands.clean));
+        commands.push(("cwd", &build_cwd));
+        for (field, value) in commands {
+            for alias in unresolved_aliases(&restore_str(value, &self.aliases)) {
+                report(format!("Unknown alias {{{}}} in the {} command", alias, field));
+            }
+        }
+
+        for (field, value) in self.layout.entries() {
+            for alias in unresolved_aliases(&restore_str(value, &self.aliases)) {
+                report(format!("Unknown alias {{{}}} in the {} of the layout", alias, field));
+            }
+        }
+
+        for zip_with in &self.zip_with {
+            for alias in unresolved_aliases(&restore_str(zip_with, &self.aliases)) {
+                report(format!("Unknown alias {{{}}} in zip_with entry {}", alias, zip_with));
+            }
+        }
+
+        for duplicate in self.duplicate_names() {
+            report(format!("{}, their logs and results would overwrite each other", duplicate));
+        }
+        for problem in self.dependency_problems() {
+            report(problem);
+        }
+
+        for cmd_env in &self.cmd_envs() {
+            let name = cmd_env.name();
+            if name.trim().is_empty() {
+                report(format!("A computation running '{}' has no name", cmd_env.cmd.cmd));
+            }
+            if cmd_env.cmd.cmd.trim().is_empty() {
+                report(format!("The computation {} has no command", name));
+            }
+            for alias in unresolved_aliases(&name) {
+                report(format!("Unknown alias {{{}}} in the name of the computation {}", alias, cmd_env.cmd.name));
+            }
+            for alias in unresolved_aliases(&restore_str(&cmd_env.cmd.cmd, &cmd_env.aliases)) {
+                report(format!("Unknown alias {{{}}} in the command of the computation {}", alias, name));
+            }
+            let fields = vec![
+                ("cwd", cmd_env.cmd.cwd.as_ref()),
+                ("when condition", cmd_env.cmd.when.as_ref()),
+                ("before hook", cmd_env.cmd.before.as_ref().or(self.commands.before.as_ref())),
+                ("after hook", cmd_env.cmd.after.as_ref().or(self.commands.after.as_ref())),
+            ];
+            let build_steps = cmd_env.cmd.build.as_ref().map(|it| it.steps()).unwrap_or_default();
+            let fields = fields.into_iter().chain(build_steps.into_iter().map(|it| ("build", Some(it))));
+            for (field, value) in fields {
+                for alias in value.map(|it| unresolved_aliases(&restore_str(it, &cmd_env.aliases))).unwrap_or_default() {
+                    report(format!("Unknown alias {{{}}} in the {} of the computation {}", alias, field, name));
+                }
+            }
+            let columns = cmd_env.cmd.metrics.iter().map(|it| ("metric", it))
+                .chain(cmd_env.cmd.checkpoints.iter().map(|it| ("checkpoint", it)));
+            for (kind, (column, pattern)) in columns {
+                if HEADERS.contains(&column.as_str()) {
+                    report(format!("The {} {} of the computation {} has the name of a column of the summary", kind, column, name));
+                }
+                if column.contains(['\t', '\n', '\r']) {
+                    report(format!("The name of the {} {:?} of the computation {} contains a tab or a line break", kind, column, name));
+                }
+                if kind == "checkpoint" && cmd_env.cmd.metrics.contains_key(column) {
+                    report(format!("The checkpoint {} of the computation {} has the name of one of its metrics", column, name));
+                }
+                if let Err(e) = Regex::new(&restore_str(pattern, &cmd_env.aliases)) {
+                    report(format!("Invalid regex for the {} {} of the computation {}: {}", kind, column, name, e));
+                }
+            }
+            for (variable, value) in &cmd_env.cmd.env {
+                if HEADERS.contains(&variable.as_str()) || cmd_env.cmd.metrics.contains_key(variable) || cmd_env.cmd.checkpoints.contains_key(variable) {
+                    report(format!("The environment variable {} of the computation {} has the name of another column of the summary", variable, name));
+                }
+                if variable.is_empty() || variable.contains(['=', '\0', '\t', '\n', '\r']) {
+                    report(format!("Invalid environment variable name {:?} for the computation {}", variable, name));
+                }
+                for alias in unresolved_aliases(&restore_str(value, &cmd_env.aliases)) {
+                    report(format!("Unknown alias {{{}}} in the environment variable {} of the computation {}", alias, variable, name));
+                }
+            }
+            if let Some(pattern) = &self.partial_result {
+                if let Err(e) = Regex::new(&restore_str(pattern, &cmd_env.aliases)) {
+                    report(format!("Invalid partial_result regex for the computation {}: {}", name, e));
+                }
+            }
+        }
+
+        problems
+    }
+
+    pub fn computation_names(&self) -> Vec<String> {
+        self.cmd_envs().iter().map(CmdEnv::name).collect()
+    }
+
+    pub fn is_selected(&self, name: &str) -> bool {
+        self.selection.as_ref().is_none_or(|selection| selection.contains(name))
+    }
+
+    /// Names of the metric columns of the summary: the metrics and checkpoints of all the computations, sorted
+    pub fn metric_columns(&self) -> Vec<String> {
+        self.cmd_envs().iter()
+            .flat_map(|cmd_env| cmd_env.cmd.metrics.keys()
+                .chain(cmd_env.cmd.checkpoints.keys())
+                .chain(cmd_env.cmd.env.keys())
+                .cloned()
+                .collect::<Vec<_>>())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
     fn cmd_envs(&self) -> Vec<CmdEnv> {
         let mut project_experiments = Vec::new();
         for job in &self.experiments {
@@ -114,19 +465,48 @@ impl Project {
     pub fn unlock_failed(&self) {
         for experiment in &self.cmd_envs() {
             if experiment.is_locked() && experiment.has_err_tag() {
-                eprintln!("Unlocking {}", experiment.name());
-                fs::remove_dir_all(&experiment.log_dir())
-                    .expect(&format!("Cannot remove the log directory for {}", experiment.name()));
+                info!("Unlocking {}", experiment.name());
+                if self.debug {
+                    // The logs of the failed run are kept next to the new ones
+                    let log_dir = experiment.log_dir();
+                    let mut kept = log_dir.clone().into_os_string();
+                    kept.push(format!(".failed-{}", Local::now().format("%Y-%m-%dT%H-%M-%S")));
+                    debug!("Keeping the logs of the failed run in {}", Path::new(&kept).display());
+                    fs::rename(&log_dir, &kept)
+                        .unwrap_or_else(|_| panic!("Cannot move the log directory for {}", experiment.name()));
+                } else {
+                    fs::remove_dir_all(experiment.log_dir())
+                        .unwrap_or_else(|_| panic!("Cannot remove the log directory for {}", experiment.name()));
+                }
+            }
+        }
+    }
+
+    /// Skipped computations are evaluated again by every run, their condition or dependencies may have changed.
+    /// The logs of their `when` condition are kept until then, and their rows are removed from the summary
+    /// so that each one only has the row of its last evaluation.
+    pub fn unlock_skipped(&self) {
+        let mut unlocked = HashSet::new();
+        for experiment in &self.cmd_envs() {
+            if experiment.is_locked() && experiment.has_skipped_tag() && self.is_selected(&experiment.name()) {
+                debug!("Unlocking {}", experiment.name());
+                experiment.unlock_skipped();
+                unlocked.insert(experiment.name());
             }
         }
+
+        if !unlocked.is_empty() && Path::new(&self.summary_file).exists() {
+            self.remove_summary_rows(&unlocked)
+                .expect("Cannot remove the skipped computations from the summary file");
+        }
     }
 
     pub fn unlock_timeout(&self) {
         for experiment in &self.cmd_envs() {
             if experiment.is_locked() && experiment.has_timeout_tag() {
-                eprintln!("Unlocking {}", experiment.name());
-                fs::remove_dir_all(&experiment.log_dir())
-                    .expect(&format!("Cannot remove the log directory for {}", experiment.name()));
+                info!("Unlocking {}", experiment.name());
+                fs::remove_dir_all(experiment.log_dir())
+                    .unwrap_or_else(|_| panic!("Cannot remove the log directory for {}", experiment.name()));
             }
         }
     }
@@ -134,9 +514,9 @@ impl Project {
     pub fn unlock_in_progress(&self) {
         for experiment in &self.cmd_envs() {
             if experiment.is_locked() && !experiment.has_done_tag() {
-                eprintln!("Unlocking {}", experiment.name());
-                fs::remove_dir_all(&experiment.log_dir())
-                    .expect(&format!("Cannot remove the log directory for {}", experiment.name()));
+                info!("Unlocking {}", experiment.name());
+                fs::remove_dir_all(experiment.log_dir())
+                    .unwrap_or_else(|_| panic!("Cannot remove the log directory for {}", experiment.name()));
             }
         }
     }
@@ -153,11 +533,107 @@ impl Project {
         }
     }
 
-    pub fn build(&self) {
-        if !Path::new(&self.source_directory).exists() {
+    /// Builds the sources, unless they were already built with the same command and did not change since.
+    pub fn build(&self, force: bool) {
+        let source_directory = Path::new(&self.source_directory);
+        if !source_directory.exists() {
             panic!("The source folder doesn't exists. Try using the --git option to fetch the sources.");
         }
+
+        let info_file = Path::new(&self.working_directory).join("last_build.ron");
+        let build_command = self.commands.build_command_line(&self.aliases);
+        if !force {
+            if let Some(info) = BuildInfo::load(&info_file) {
+                if fingerprint(&build_command, source_directory).as_ref() == Some(&info.fingerprint) {
+                    info!("build up to date.");
+                    return;
+                }
+            }
+        }
+
+        let start = Instant::now();
         self.commands.run_build(&self.source_directory, &self.aliases);
+        let duration = start.elapsed();
+        info!("Build done in {}", humantime::format_duration(Duration::from_millis(duration.as_millis() as u64)));
+
+        if let Some(fingerprint) = fingerprint(&build_command, source_directory) {
+            BuildInfo { fingerprint, duration, finished_at: Local::now().to_rfc3339() }.save(&info_file);
+        }
+    }
+
+    /// Runs the `setup` command if any, returns `false` if it failed
+    pub fn setup(&self) -> bool {
+        match &self.commands.setup {
+            Some(setup) => self.commands.run_phase("setup", setup, &self.source_directory, &self.aliases, &Path::new(&self.log_directory).join("setup.log")),
+            None => true,
+        }
+    }
+
+    pub fn teardown(&self) {
+        if let Some(teardown) = &self.commands.teardown {
+            self.commands.run_phase("teardown", teardown, &self.source_directory, &self.aliases, &Path::new(&self.log_directory).join("teardown.log"));
+        }
+    }
+
+    /// Prints every computation with its command once the aliases are restored, its timeout and its status.
+    pub fn display_list(&self, filters: &Option<Vec<String>>) {
+        let timeout = self.global_timeout
+            .map(|it| humantime::format_duration(it).to_string())
+            .unwrap_or_else(|| String::from("-"));
+        let rows = self.cmd_envs().iter()
+            .filter(|it| it.match_any(filters))
+            .map(|it| (it.name(), it.status().0.colored(), restore_str(&it.cmd.cmd, &it.aliases)))
+            .collect::<Vec<_>>();
+
+        let name_size = rows.iter().map(|it| it.0.len()).max().unwrap_or(0).max("name".len());
+        let status_size = "No started".len();
+        let timeout_size = timeout.len().max("timeout".len());
+        println!("{:name_size$}   {:status_size$}   {:timeout_size$}   command", "name", "status", "timeout",
+                 name_size = name_size, status_size = status_size, timeout_size = timeout_size);
+        for (name, status, command) in &rows {
+            println!("{:name_size$}   {:status_size$}   {:timeout_size$}   {}", name, status, timeout, command,
+                     name_size = name_size, status_size = status_size, timeout_size = timeout_size);
+        }
+    }
+
+    /// Prints what the computations named `name` would run, without running them.
+    /// Returns `false` when there is no such computation.
+    pub fn display_command(&self, name: &str) -> bool {
+        let names = Some(vec![name.to_owned()]);
+        let cmd_envs = self.cmd_envs().into_iter()
+            .filter(|it| it.match_any(&names))
+            .collect::<Vec<_>>();
+
+        for (i, cmd_env) in cmd_envs.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("name: {}", cmd_env.name());
+            println!("working directory: {}", cmd_env.working_directory());
+            if let Some(timeout) = self.global_timeout {
+                println!("timeout: {}", humantime::format_duration(timeout));
+            }
+            println!("aliases:");
+            let mut aliases = cmd_env.aliases.iter().collect::<Vec<_>>();
+            aliases.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+            for (key, value) in aliases {
+                println!("  {}={}", key, value);
+            }
+            println!("$ bash -c {:?}", self.commands.executable_script(&cmd_env.aliases, &cmd_env.cmd.cmd));
+        }
+
+        !cmd_envs.is_empty()
+    }
+
+    /// Number of computations in each status, including the ones without any computation
+    pub fn status_counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = Status::ALL.iter()
+            .map(|status| (status.label(), 0))
+            .collect::<BTreeMap<_, _>>();
+        for cmd_env in &self.cmd_envs() {
+            *counts.entry(cmd_env.status().0.label()).or_default() += 1;
+        }
+        counts
     }
 
     pub fn display_status(&self, filters: &Option<Vec<String>>) {
@@ -167,33 +643,22 @@ impl Project {
         let mut nb_timeouts = 0;
         let mut nb_done = 0;
         let mut nb_running = 0;
+        let mut nb_skipped = 0;
 
         let cmd_envs = self.cmd_envs();
         for cmd_env in &cmd_envs {
             if cmd_env.match_any(filters) {
-                let (status, date) = if cmd_env.is_locked() {
-                    if cmd_env.has_err_tag() {
-                        let creation_date = cmd_env.tag_creation_date(&CmdEnv::ERR_TAG);
-                        nb_failures += 1;
-                        ("Failed".red(), creation_date)
-                    } else if cmd_env.has_timeout_tag() {
-                        let creation_date = cmd_env.tag_creation_date(&CmdEnv::TIMEOUT_TAG);
-                        nb_timeouts += 1;
-                        ("Timeout".yellow(), creation_date)
-                    } else if cmd_env.has_done_tag() {
-                        let creation_date = cmd_env.tag_creation_date(&CmdEnv::DONE_TAG);
-                        nb_done += 1;
-                        ("Done".green(), creation_date)
-                    } else {
-                        let creation_date = cmd_env.tag_creation_date(&CmdEnv::LOCK_TAG);
-                        nb_running += 1;
-                        ("Running".blue(), creation_date)
-                    }
-                } else {
-                    ("No started".black(), None)
-                };
+                let (status, date) = cmd_env.status();
+                match status {
+                    Status::Failed => nb_failures += 1,
+                    Status::Timeout => nb_timeouts += 1,
+                    Status::Done => nb_done += 1,
+                    Status::Running => nb_running += 1,
+                    Status::Skipped => nb_skipped += 1,
+                    Status::NotStarted => {}
+                }
                 let date_str = date.map(|it| it.format("%F %R").to_string()).unwrap_or(String::new());
-                println!("{:<40}\t{:<40}\t{:<40}", cmd_env.name(), &status, &date_str);
+                println!("{:<40}\t{:<40}\t{:<40}", cmd_env.name(), &status.colored(), &date_str);
             }
         }
 
@@ -203,11 +668,19 @@ impl Project {
         eprintln!("{:>8} {:>5}/{}", "Running", nb_running.to_string().blue(), cmd_envs.len());
         eprintln!("{:>8} {:>5}/{}", "Timeout", nb_timeouts.to_string().yellow(), cmd_envs.len());
         eprintln!("{:>8} {:>5}/{}", "Failures", nb_failures.to_string().red(), cmd_envs.len());
+        eprintln!("{:>8} {:>5}/{}", "Skipped", nb_skipped.to_string().cyan(), cmd_envs.len());
     }
 
-    pub fn fetch_sources(&self) {
-        let folder = Path::new(&self.source_directory);
-        if folder.exists() && folder.is_dir() && folder.read_dir().unwrap().count() != 0 {
+    /// Fetches the sources then the dependencies, `jobs` of them at a time. Existing clones are updated in place,
+    /// unless `force` is set, in which case the source directory is wiped and cloned again.
+    pub fn fetch_sources(&self, jobs: usize, force: bool) {
+        let versioning = &self.versioning;
+        let source_directory = Path::new(&self.source_directory);
+        let is_non_empty = source_directory.is_dir() && source_directory.read_dir().unwrap().count() != 0;
+
+        if is_non_empty && force {
+            fs::remove_dir_all(source_directory).expect("Cannot delete source directory");
+        } else if is_non_empty && !is_clone_of(source_directory, &versioning.url) {
             let mut response = String::new();
             loop {
                 print!("The source directory is non empty. Would you erase it and fetch the sources again ? (y/N): ");
@@ -227,49 +700,57 @@ impl Project {
             }
         }
 
-        if self.versioning.url.starts_with("file:") {
-            copy_dir_all(&self.versioning.url["file:".len()..], &self.source_directory)
-                .expect("Cannot copy the sources to the working directory");
-        } else if self.versioning.url.starts_with("scp:") {
-            Command::new("scp")
-                .current_dir(&self.working_directory)
-                .arg("-r")
-                .arg(&self.versioning.url["scp:".len()..])
-                .arg("src")
-                .stdin(Stdio::inherit())
-                .stdout(Stdio::inherit())
-                .status()
-                .expect("Cannot copy the sources using the scp command");
+        let fetched = if is_clone_of(source_directory, &versioning.url) {
+            info!("Updating the existing clone in {}", source_directory.display());
+            update(source_directory, &versioning.commit, versioning.sub_modules, versioning.clean_before_update, jobs)
         } else {
-            Command::new("git")
-                .current_dir(&self.working_directory)
-                .arg("clone")
-                .arg(&self.versioning.url)
-                .arg("src")
-                .status()
-                .expect("Cannot clone the remove git project");
-
-            if let Some(commit) = &self.versioning.commit {
-                Command::new("git")
-                    .current_dir(&self.source_directory)
-                    .arg("checkout")
-                    .arg(&commit)
-                    .status()
-                    .expect("Cannot execute the git checkout command");
+            match &versioning.cache_directory {
+                Some(cache) => {
+                    let cache = Path::new(&self.working_directory).parent().unwrap_or(Path::new(".")).join(cache);
+                    fs::create_dir_all(&cache).expect("Cannot create the cache directory");
+                    fetch_cached(&cache, &versioning.url, &versioning.commit, versioning.sub_modules, source_directory, jobs)
+                }
+                None => fetch(&versioning.url, &versioning.commit, versioning.sub_modules, source_directory, jobs),
             }
+        };
+        if let Err(e) = fetched {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+
+        let pool = ThreadPool::new(jobs);
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let clean = versioning.clean_before_update;
+        for dependency in &versioning.dependencies {
+            let dependency = dependency.clone();
+            let destination = source_directory.join(&dependency.directory);
+            let errors = errors.clone();
+            pool.execute(move || {
+                let fetched = if is_clone_of(&destination, &dependency.url) {
+                    info!("Updating {} in {}", dependency.url, destination.display());
+                    update(&destination, &dependency.commit, dependency.sub_modules, clean, 1)
+                } else {
+                    info!("Fetching {} into {}", dependency.url, destination.display());
+                    fetch(&dependency.url, &dependency.commit, dependency.sub_modules, &destination, 1)
+                };
+                if let Err(e) = fetched {
+                    errors.lock().unwrap().push(format!("{}: {}", dependency.url, e));
+                }
+            });
+        }
+        pool.join();
 
-            if self.versioning.sub_modules {
-                Command::new("git")
-                    .current_dir(&self.source_directory)
-                    .args(&["submodule", "update", "--init"])
-                    .status()
-                    .expect("Cannot initialize the sub modules");
+        let errors = errors.lock().unwrap();
+        if !errors.is_empty() {
+            for error in errors.iter() {
+                error!("{}", error);
             }
+            std::process::exit(1);
         }
     }
 }
 
-fn copy_dir_all<PathSrc, PathDest>(source: PathSrc, destination: PathDest) -> io::Result<()>
+pub(crate) fn copy_dir_all<PathSrc, PathDest>(source: PathSrc, destination: PathDest) -> io::Result<()>
     where PathSrc: AsRef<Path>, PathDest: AsRef<Path>
 {
     fs::create_dir_all(&destination)?;
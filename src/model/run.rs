@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use log::{error, info, warn};
+use threadpool::ThreadPool;
+use crate::model::children::Children;
+use crate::model::commands::kill;
+use crate::model::computation_result::RunReport;
+use crate::model::configuration::read_summary;
+use crate::model::lock::{LockOptions, ProjectLock};
+use crate::model::output::SummaryWriter;
+use crate::model::project::Project;
+use crate::tools::available_cores;
+use crate::tools::junit::junit_report;
+use crate::tools::notify::notify;
+use crate::tools::prometheus::RunMetrics;
+
+/// What `run` does besides running the computations that are not started yet, see `whitesmith run --help`
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    pub with_in_progress: bool,
+    pub with_timeout: bool,
+    pub with_failure: bool,
+    pub metrics_file: Option<PathBuf>,
+}
+
+/// State shared by the computations of a run and the signal handlers driving it. Each run has its own,
+/// the command line creates one.
+#[derive(Default)]
+pub struct RunState {
+    abort: AtomicBool,
+    pause: AtomicBool,
+    /// The computations in progress, killed on abort
+    pub children: Children,
+}
+
+impl RunState {
+    /// Lets the computations in progress finish but starts no new one. Returns whether the run was already aborted
+    pub fn abort(&self) -> bool {
+        self.abort.swap(true, Ordering::SeqCst)
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.abort.load(Ordering::SeqCst)
+    }
+
+    /// No new computation starts while the run is paused
+    pub fn set_paused(&self, pause: bool) {
+        self.pause.store(pause, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause.load(Ordering::SeqCst)
+    }
+
+    /// Kills the computations in progress
+    pub fn kill_children(&self) {
+        // Signalled right away from the snapshot, so that a pid has little time to be reused once its child is reaped
+        for child in self.children.snapshot() {
+            info!("Send Kill to {}", child);
+            kill(child);
+        }
+    }
+}
+
+/// Takes the lock of the project, or returns why it cannot be taken, e.g. another instance works on it
+pub fn lock_project(project: &Project, options: &LockOptions) -> Result<ProjectLock, String> {
+    ProjectLock::acquire(&project.working_directory, options)
+}
+
+/// Runs the computations of the project on `nb_threads` threads, with the setup and teardown scripts around them,
+/// and returns their results, or why the run cannot start. The caller holds the lock of the project (see `lock_project`)
+/// and may abort the run through `state`, e.g. on Ctrl-C: the computations in progress finish, or are killed with
+/// `RunState::kill_children`, and no other one starts.
+pub fn run(project: Arc<Project>, options: &RunOptions, state: &Arc<RunState>) -> Result<RunReport, String> {
+    let start = Instant::now();
+    if project.requires_overrides() {
+        return Err(String::from("Some aliases of the configuration must be overridden"));
+    }
+
+    let duplicates = project.duplicate_names();
+    if !duplicates.is_empty() {
+        return Err(format!(
+            "{}\nAborting the run since the logs and results of computations sharing a name would overwrite each other",
+            duplicates.join("\n"),
+        ));
+    }
+
+    let problems = project.dependency_problems();
+    if !problems.is_empty() {
+        return Err(problems.join("\n"));
+    }
+
+    project.unlock_skipped();
+
+    if options.with_in_progress {
+        project.unlock_in_progress();
+    }
+
+    if options.with_timeout {
+        project.unlock_timeout();
+    }
+
+    if options.with_failure {
+        project.unlock_failed();
+    }
+
+    if let Some(limits) = &project.limits {
+        let errors = limits.check();
+        if !errors.is_empty() {
+            if project.strict_limits {
+                return Err(format!("{}\nAborting the run since strict_limits is enabled", errors.join("\n")));
+            }
+            for error in &errors {
+                warn!("{}", error);
+            }
+            warn!("The computations will run without the limits above");
+        }
+    }
+
+    project.check_fingerprint(true);
+    let summary = SummaryWriter::open(Path::new(&project.summary_file), project.metric_columns())
+        .map_err(|e| format!("Cannot open the summary file {}: {}", project.summary_file, e))?;
+    let summary = Arc::new(summary);
+
+    if !project.setup() {
+        project.teardown();
+        return Err(String::from("The setup script failed"));
+    }
+
+    let nb_threads = project.nb_threads.unwrap_or_else(available_cores);
+    info!("Running with {} thread(s)", nb_threads);
+
+    let report = project.run(ThreadPool::new(nb_threads), summary.clone(), state);
+    project.teardown();
+    if options.metrics_file.is_some() || project.notify_url.is_some() {
+        let metrics = run_metrics(&project, &summary, start);
+        if let Some(metrics_file) = &options.metrics_file {
+            if let Err(e) = metrics.write(metrics_file) {
+                error!("Cannot write the metrics file {}: {}", metrics_file.display(), e);
+            }
+        }
+        if let Some(url) = &project.notify_url {
+            notify(url, project.notify_on, &metrics, state.is_aborted());
+        }
+    }
+    Ok(report)
+}
+
+/// Name of the project in the reports, the one of its summary file
+fn project_name(project: &Project) -> String {
+    Path::new(&project.summary_file).file_stem()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+pub fn write_junit(project: &Project, path: &Path, is_zip_archive: bool, output: &Path) {
+    let summary = read_summary(path, &project.summary_file, is_zip_archive).unwrap_or_default();
+    let suite = project_name(project);
+    let written = junit_report(&suite, &summary)
+        .and_then(|report| fs::write(output, report).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        error!("Cannot write the JUnit report {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+}
+
+fn run_metrics(project: &Project, summary: &SummaryWriter, start: Instant) -> RunMetrics {
+    RunMetrics {
+        project: project_name(project),
+        results: summary.results(),
+        statuses: project.status_counts(),
+        duration: start.elapsed(),
+    }
+}
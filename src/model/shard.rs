@@ -0,0 +1,125 @@
+use std::collections::{BTreeSet, HashSet};
+use std::str::FromStr;
+use clap::ValueEnum;
+
+/// The `index`th of `count` buckets of computations, written `index/count` and counted from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: usize,
+    pub count: usize,
+}
+
+/// How the computations are distributed over the shards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ShardMode {
+    /// The first computations go to the first shard, the next ones to the second shard...
+    Contiguous,
+    /// The first computation goes to the first shard, the second one to the second shard...
+    Striped,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| s.trim().parse::<usize>().ok();
+        match s.split_once('/').map(|(index, count)| (parse(index), parse(count))) {
+            Some((Some(index), Some(count))) if 1 <= index && index <= count => Ok(Shard { index, count }),
+            _ => Err(format!("Cannot parse {} as a shard, expected i/n with 1 <= i <= n (e.g. 2/4)", s)),
+        }
+    }
+}
+
+impl Shard {
+    /// Names of the computations of the shard. The names are sorted, then shuffled with `seed` if any,
+    /// so that every machine computes the same buckets whatever the order of the configuration.
+    pub fn select<I: IntoIterator<Item=String>>(&self, names: I, mode: ShardMode, seed: Option<u64>) -> HashSet<String> {
+        let mut names = names.into_iter().collect::<BTreeSet<_>>().into_iter().collect::<Vec<_>>();
+        if let Some(seed) = seed {
+            names.sort_by_cached_key(|name| {
+                let mut hasher = crc32fast::Hasher::new();
+                hasher.update(&seed.to_le_bytes());
+                hasher.update(name.as_bytes());
+                (hasher.finalize(), name.clone())
+            });
+        }
+
+        let len = names.len();
+        names.into_iter()
+            .enumerate()
+            .filter(|(position, _)| match mode {
+                ShardMode::Contiguous => position * self.count / len.max(1) == self.index - 1,
+                ShardMode::Striped => position % self.count == self.index - 1,
+            })
+            .map(|(_, name)| name)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::{Shard, ShardMode};
+
+    fn names(count: usize) -> Vec<String> {
+        (0..count).map(|it| format!("c{:02}", it)).collect()
+    }
+
+    fn sorted(selection: HashSet<String>) -> Vec<String> {
+        let mut selection = selection.into_iter().collect::<Vec<_>>();
+        selection.sort();
+        selection
+    }
+
+    #[test]
+    fn shards_are_parsed_from_index_and_count() {
+        assert_eq!("2/4".parse(), Ok(Shard { index: 2, count: 4 }));
+        assert_eq!(" 1 / 1 ".parse(), Ok(Shard { index: 1, count: 1 }));
+        assert_eq!("4/4".parse(), Ok(Shard { index: 4, count: 4 }));
+        for invalid in ["0/4", "5/4", "1/0", "0/0", "a/2", "2", "-1/2", ""] {
+            assert!(invalid.parse::<Shard>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn shards_are_disjoint_and_cover_every_computation() {
+        for mode in [ShardMode::Contiguous, ShardMode::Striped] {
+            for seed in [None, Some(7)] {
+                for len in [0, 1, 3, 10, 11] {
+                    for count in 1..=5 {
+                        let mut covered = Vec::new();
+                        for index in 1..=count {
+                            covered.extend(Shard { index, count }.select(names(len), mode, seed));
+                        }
+                        covered.sort();
+                        assert_eq!(covered, names(len), "{:?} {:?} {} computations in {} shards", mode, seed, len, count);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn computations_are_distributed_by_mode() {
+        let first = |mode| sorted(Shard { index: 1, count: 3 }.select(names(7), mode, None));
+        assert_eq!(first(ShardMode::Contiguous), ["c00", "c01", "c02"]);
+        assert_eq!(first(ShardMode::Striped), ["c00", "c03", "c06"]);
+    }
+
+    #[test]
+    fn shards_do_not_depend_on_the_order_of_the_configuration() {
+        let mut reversed = names(10);
+        reversed.reverse();
+        for seed in [None, Some(7)] {
+            let shard = Shard { index: 2, count: 3 };
+            assert_eq!(
+                shard.select(names(10), ShardMode::Contiguous, seed),
+                shard.select(reversed.clone(), ShardMode::Contiguous, seed),
+            );
+        }
+
+        let shuffled = sorted(Shard { index: 1, count: 2 }.select(names(10), ShardMode::Contiguous, Some(7)));
+        assert_ne!(shuffled, names(5));
+        assert_eq!(shuffled.len(), 5);
+    }
+}
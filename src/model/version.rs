@@ -1,10 +1,58 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+/// Fields are compared in order, so the derived ordering is the one of `major.minor.patch`
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Version(pub u8, pub u8, pub u8);
 
-impl ToString for Version {
-    fn to_string(&self) -> String {
-        format!("{}.{}.{}", self.0, self.1, self.2)
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
     }
-}
\ No newline at end of file
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.trim().split('.')
+            .map(|part| part.parse::<u8>().map_err(|e| format!("Cannot parse {} as a version, {}: {}", s, part, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+        match parts.as_slice() {
+            [major, minor, patch] => Ok(Version(*major, *minor, *patch)),
+            _ => Err(format!("Cannot parse {} as a version, expected MAJOR.MINOR.PATCH", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::migration::{CURRENT_VERSION, MAX_VERSION, MIN_VERSION};
+    use super::Version;
+
+    #[test]
+    fn versions_are_parsed_from_their_display() {
+        for version in [Version(0, 0, 0), Version(0, 6, 2), Version(1, 12, 255), MIN_VERSION, MAX_VERSION] {
+            assert_eq!(version.to_string().parse(), Ok(version));
+        }
+        assert_eq!(" 0.5.3 ".parse(), Ok(Version(0, 5, 3)));
+        assert_eq!(env!("CARGO_PKG_VERSION").parse(), Ok(CURRENT_VERSION));
+    }
+
+    #[test]
+    fn malformed_versions_are_refused() {
+        for invalid in ["1.2", "1.2.3.4", "1.x.0", "256.0.0", "-1.0.0", "1..0", ""] {
+            let error = invalid.parse::<Version>().expect_err(invalid);
+            assert!(error.starts_with(&format!("Cannot parse {} as a version", invalid)), "{}", error);
+        }
+    }
+
+    #[test]
+    fn versions_are_ordered_by_major_minor_then_patch() {
+        assert!(Version(0, 5, 9) < Version(0, 6, 0));
+        assert!(Version(0, 9, 9) < Version(1, 0, 0));
+        assert!(Version(0, 6, 1) < Version(0, 6, 2));
+        assert!(MIN_VERSION <= CURRENT_VERSION && CURRENT_VERSION < MAX_VERSION);
+    }
+}
@@ -1,4 +1,9 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
 use serde::{Serialize, Deserialize};
+use log::{info, warn};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Versioning {
@@ -7,4 +12,213 @@ pub struct Versioning {
     pub commit: Option<String>,
     #[serde(default)]
     pub sub_modules: bool,
-}
\ No newline at end of file
+    /// Other repositories fetched in parallel inside the source directory, once the main one is fetched
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    /// Directory where the fetched git trees are kept by commit, relative to the configuration file.
+    /// Fetching a commit that is already in the cache copies it instead of cloning it again.
+    #[serde(default)]
+    pub cache_directory: Option<String>,
+    /// Discards the local changes and untracked files before updating an existing clone
+    #[serde(default = "default_clean_before_update")]
+    pub clean_before_update: bool,
+}
+
+fn default_clean_before_update() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Dependency {
+    pub url: String,
+    #[serde(default)]
+    pub commit: Option<String>,
+    #[serde(default)]
+    pub sub_modules: bool,
+    /// Destination, relative to the source directory
+    pub directory: String,
+}
+
+/// Fetches `url` into `destination`. The url is either `file:<path>`, `scp:<remote path>` or a git url.
+/// `jobs` is the number of submodules fetched in parallel.
+pub fn fetch(url: &str, commit: &Option<String>, sub_modules: bool, destination: &Path, jobs: usize) -> Result<(), String> {
+    if let Some(path) = url.strip_prefix("file:") {
+        return crate::model::project::copy_dir_all(path, destination)
+            .map_err(|e| format!("Cannot copy {} to {}: {}", path, destination.display(), e));
+    }
+
+    if let Some(remote) = url.strip_prefix("scp:") {
+        return run(Command::new("scp")
+            .arg("-r")
+            .arg(remote)
+            .arg(destination)
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::inherit()), "Cannot copy the sources using the scp command");
+    }
+
+    run(Command::new("git").arg("clone").arg(url).arg(destination), "Cannot clone the remote git project")?;
+
+    if let Some(commit) = commit {
+        run(Command::new("git")
+            .current_dir(destination)
+            .arg("checkout")
+            .arg(commit), "Cannot execute the git checkout command")?;
+    }
+
+    if sub_modules {
+        run(Command::new("git")
+            .current_dir(destination)
+            .args(["submodule", "update", "--init", "--jobs", &jobs.to_string()]), "Cannot initialize the sub modules")?;
+    }
+
+    Ok(())
+}
+
+/// Whether `directory` is the root of a clone of `url`, which can then be updated instead of cloned again.
+pub fn is_clone_of(directory: &Path, url: &str) -> bool {
+    if url.starts_with("file:") || url.starts_with("scp:") || !directory.join(".git").exists() {
+        return false;
+    }
+    let origin = Command::new("git")
+        .current_dir(directory)
+        .args(["remote", "get-url", "origin"])
+        .stderr(Stdio::null())
+        .output();
+    matches!(origin, Ok(output) if output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == url)
+}
+
+/// Brings an existing clone to `commit`, or to the remote default branch when no commit is given.
+pub fn update(directory: &Path, commit: &Option<String>, sub_modules: bool, clean: bool, jobs: usize) -> Result<(), String> {
+    let git = |args: &[&str], error: &str| run(Command::new("git").current_dir(directory).args(args), error);
+
+    git(&["fetch", "--tags", "origin"], "Cannot execute the git fetch command")?;
+
+    if clean {
+        git(&["reset", "--hard", "--quiet"], "Cannot reset the sources")?;
+        git(&["clean", "-fdx", "--quiet"], "Cannot remove the untracked files")?;
+    }
+
+    // A branch name refers to the remote branch, the local one is stale
+    let target = match commit {
+        Some(commit) => {
+            let remote_branch = format!("origin/{}", commit);
+            let is_branch = Command::new("git")
+                .current_dir(directory)
+                .args(["rev-parse", "--verify", "--quiet", &remote_branch])
+                .stdout(Stdio::null())
+                .status()
+                .is_ok_and(|it| it.success());
+            if is_branch { remote_branch } else { commit.clone() }
+        }
+        None => String::from("origin/HEAD"),
+    };
+    git(&["checkout", "--quiet", "--detach", &target], "Cannot execute the git checkout command")?;
+
+    if sub_modules {
+        git(&["submodule", "update", "--init", "--jobs", &jobs.to_string()], "Cannot initialize the sub modules")?;
+    }
+
+    Ok(())
+}
+
+/// Same as `fetch`, but looks for the tree in `cache` first and stores it there after a clone.
+/// Only git urls are cached, under the SHA of the requested commit. A cached tree whose checksum
+/// does not match the one recorded when it was stored is discarded and fetched again.
+pub fn fetch_cached(cache: &Path, url: &str, commit: &Option<String>, sub_modules: bool, destination: &Path, jobs: usize) -> Result<(), String> {
+    if url.starts_with("file:") || url.starts_with("scp:") {
+        return fetch(url, commit, sub_modules, destination, jobs);
+    }
+
+    let sha = match resolve_commit(url, commit) {
+        Some(sha) => sha,
+        None => return fetch(url, commit, sub_modules, destination, jobs),
+    };
+    let key = if sub_modules { format!("{}-submodules", sha) } else { sha };
+    let entry = cache.join(&key);
+    let checksum_file = cache.join(format!("{}.crc32", key));
+
+    if entry.is_dir() {
+        let expected = fs::read_to_string(&checksum_file).unwrap_or_default();
+        match checksum(&entry) {
+            Ok(actual) if actual == expected.trim() => {
+                info!("Using the cached sources of {}", key);
+                return crate::model::project::copy_dir_all(&entry, destination)
+                    .map_err(|e| format!("Cannot copy {} to {}: {}", entry.display(), destination.display(), e));
+            }
+            _ => {
+                warn!("The cached sources of {} are corrupted, fetching them again", key);
+                let _ = fs::remove_dir_all(&entry);
+                let _ = fs::remove_file(&checksum_file);
+            }
+        }
+    }
+
+    fetch(url, commit, sub_modules, destination, jobs)?;
+
+    // A failure to fill the cache does not prevent the benchmark from running
+    if let Err(e) = store(destination, &entry, &checksum_file) {
+        warn!("Cannot store the sources in the cache {}: {}", cache.display(), e);
+    }
+    Ok(())
+}
+
+/// SHA of the commit, which is either given as a full SHA or resolved (branch, tag, HEAD) with `git ls-remote`.
+fn resolve_commit(url: &str, commit: &Option<String>) -> Option<String> {
+    let reference = commit.as_deref().unwrap_or("HEAD");
+    if reference.len() == 40 && reference.chars().all(|it| it.is_ascii_hexdigit()) {
+        return Some(reference.to_lowercase());
+    }
+
+    let output = Command::new("git")
+        .args(["ls-remote", url, reference])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() { return None; }
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .next()
+        .map(String::from)
+}
+
+fn store(source: &Path, entry: &Path, checksum_file: &Path) -> io::Result<()> {
+    // Copied next to the entry then renamed, so that an interrupted copy is never taken for a cached tree
+    let partial = entry.with_extension("partial");
+    if partial.exists() {
+        fs::remove_dir_all(&partial)?;
+    }
+    crate::model::project::copy_dir_all(source, &partial)?;
+    let checksum = checksum(&partial)?;
+    fs::rename(&partial, entry)?;
+    fs::write(checksum_file, checksum)
+}
+
+/// CRC32 of the relative paths and contents of every file of the tree, visited in a stable order.
+pub fn checksum(directory: &Path) -> io::Result<String> {
+    fn visit(root: &Path, directory: &Path, hasher: &mut crc32fast::Hasher) -> io::Result<()> {
+        let mut entries = fs::read_dir(directory)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|it| it.file_name());
+        for entry in entries {
+            let path = entry.path();
+            hasher.update(path.strip_prefix(root).unwrap().to_string_lossy().as_bytes());
+            if entry.file_type()?.is_dir() {
+                visit(root, &path, hasher)?;
+            } else {
+                hasher.update(&fs::read(&path)?);
+            }
+        }
+        Ok(())
+    }
+
+    let mut hasher = crc32fast::Hasher::new();
+    visit(directory, directory, &mut hasher)?;
+    Ok(format!("{:08x}", hasher.finalize()))
+}
+
+fn run(command: &mut Command, error: &str) -> Result<(), String> {
+    match command.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("{} ({})", error, status)),
+        Err(e) => Err(format!("{}: {}", error, e)),
+    }
+}
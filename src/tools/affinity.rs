@@ -0,0 +1,44 @@
+/// Pins the calling thread to its own CPU the first time it is called from that thread.
+/// CPUs are handed out in turn among the ones the process is allowed to run on,
+/// and the processes spawned afterwards by the thread inherit its affinity.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread() {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use once_cell::sync::Lazy;
+    use log::warn;
+
+    static ALLOWED_CPUS: Lazy<Vec<usize>> = Lazy::new(allowed_cpus);
+    static NEXT_CPU: AtomicUsize = AtomicUsize::new(0);
+    thread_local!(static PINNED: Cell<bool> = const { Cell::new(false) });
+
+    PINNED.with(|pinned| {
+        if pinned.replace(true) || ALLOWED_CPUS.is_empty() {
+            return;
+        }
+        let cpu = ALLOWED_CPUS[NEXT_CPU.fetch_add(1, Ordering::SeqCst) % ALLOWED_CPUS.len()];
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(cpu, &mut set);
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                warn!("Cannot pin the thread to the CPU {}: {}", cpu, std::io::Error::last_os_error());
+            }
+        }
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> Vec<usize> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Vec::new();
+        }
+        (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+            .collect()
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread() {}
@@ -0,0 +1,416 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use clap::{Args, ValueEnum};
+use log::{error, info, warn};
+use ron::ser::PrettyConfig;
+use zip::CompressionMethod;
+use crate::model::{log_directory, summary_file, working_directory};
+use crate::model::commands::restore_path;
+use crate::model::layout::Layout;
+use crate::model::project::Project;
+use crate::tools::{available_cores, RecursiveZipWriter, ZipManifest};
+use threadpool::ThreadPool;
+
+// How a results archive is built, for `zip` and the backup of `clean`
+#[derive(Args, Default)]
+pub struct ZipOptions {
+    #[arg(short, long)]
+    pub zip_with: Vec<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Compression::Stored)]
+    pub compression: Compression,
+    /// Compression level, the range depends on the method (deflate: 0-9, zstd: 1-21)
+    #[arg(long)]
+    pub compression_level: Option<i32>,
+    /// Glob pattern of the files to leave out of the archive, matched against the path and the file name
+    #[arg(short, long)]
+    pub exclude: Vec<String>,
+    /// Read every entry back once the archive is written, clean is aborted if it is corrupted
+    #[arg(long)]
+    pub verify: bool,
+    /// Store the `zip_with` files under this directory with their path relative to it, so that extracting the
+    /// archive reproduces the tree (e.g. `--base {PROJECT}`). By default they keep the path they match from their pattern
+    #[arg(long)]
+    pub base: Option<PathBuf>,
+    /// What to do with the symbolic links found in the archived directories
+    #[arg(long, value_enum, default_value_t = Symlinks::Follow)]
+    pub symlinks: Symlinks,
+    /// Compress the files on several threads, which holds up to twice as many compressed files in memory.
+    /// Worth it with --compression deflate or zstd. The files above 16 MiB are still compressed one at a time
+    #[arg(long)]
+    pub parallel: bool,
+    /// Number of threads compressing with --parallel (defaults to the `nb_threads` of the configuration,
+    /// then to the number of logical CPUs)
+    #[arg(long, requires = "parallel")]
+    pub nb_threads: Option<usize>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default)]
+pub enum Compression {
+    #[default]
+    Stored,
+    Deflate,
+    Zstd,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Archive the file or the directory the link points to, a dangling link is skipped with a warning
+    #[default]
+    Follow,
+    /// Archive the link itself, it is restored as a link on extraction
+    Store,
+    /// Leave the links out of the archive
+    Skip,
+}
+
+impl From<Compression> for CompressionMethod {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::Stored => CompressionMethod::Stored,
+            Compression::Deflate => CompressionMethod::Deflated,
+            Compression::Zstd => CompressionMethod::Zstd,
+        }
+    }
+}
+
+/// Archives the results of the project: its logs, its summary, its configuration and the `zip_with` files.
+/// A project that has only been fetched or built can be archived too, without the files of a run.
+pub fn zip_project(zip_path: &Path, project: &Project, options: &ZipOptions) -> Result<(), String> {
+    let zip_file = File::create(zip_path)
+        .map_err(|e| format!("Cannot create the zip archive {}: {}", zip_path.display(), e))?;
+    let mut archive = configure(RecursiveZipWriter::new(zip_file), project, options);
+
+    // Renamed so that the archive has the same layout wherever the logs and the summary are.
+    // Both are missing until the project runs, the archive then only holds its configuration.
+    add_optional(&mut archive, Path::new(&project.log_directory), Path::new("logs"), "log directory");
+
+    let summary_name = summary_file(zip_path, &project.versioning, &project.layout, true);
+    add_optional(&mut archive, Path::new(&project.summary_file), Path::new(&summary_name), "summary file");
+
+    let running_configuration = Path::new(&project.working_directory).join("last_running_configuration.ron");
+    let build_configuration = Path::new(&project.working_directory).join("last_build_configuration.ron");
+    if running_configuration.exists() || !build_configuration.exists() {
+        add_optional(&mut archive, &running_configuration, Path::new("last_running_configuration.ron"), "running configuration");
+    } else {
+        info!("The project has been built but has not run, the archive only holds the build artifacts");
+    }
+
+    if build_configuration.exists() {
+        add_optional(&mut archive, &build_configuration, Path::new("last_build_configuration.ron"), "build configuration");
+    }
+
+    let mut archived_project = project.clone();
+    archived_project.whitesmith_version = Some(String::from(env!("CARGO_PKG_VERSION")));
+    archived_project.layout = Layout::default();
+    let serialized_project = ron::ser::to_string_pretty(&archived_project, PrettyConfig::default())
+        .map_err(|e| format!("Cannot serialize the configuration: {}", e))?;
+    archive.add_buf(serialized_project.as_bytes(), Path::new("configuration.ron"))
+        .map_err(|e| format!("Cannot add the configuration file to the zip archive: {}", e))?;
+
+    add_zip_with(&mut archive, project, options);
+    finish_archive(archive, zip_path, options)
+}
+
+/// Adds the `zip_with` files to an archive built by `zip_project`, e.g. an artifact produced after the run.
+/// The entries already in the archive are kept as they are.
+pub fn append_to_archive(zip_path: &Path, project: &Project, options: &ZipOptions) -> Result<(), String> {
+    let zip_file = OpenOptions::new().read(true).write(true).open(zip_path)
+        .map_err(|e| format!("Cannot open the zip archive {}: {}", zip_path.display(), e))?;
+    let archive = RecursiveZipWriter::new_append(zip_file)
+        .map_err(|e| format!("Cannot read the zip archive {}: {}", zip_path.display(), e))?;
+    let mut archive = configure(archive, project, options);
+
+    add_zip_with(&mut archive, project, options);
+    finish_archive(archive, zip_path, options)
+}
+
+fn configure<W: Write + Seek>(archive: RecursiveZipWriter<W>, project: &Project, options: &ZipOptions) -> RecursiveZipWriter<W> {
+    let pool = options.parallel
+        .then(|| ThreadPool::new(options.nb_threads.or(project.nb_threads).unwrap_or_else(available_cores)));
+    archive
+        .compression_method(options.compression.into())
+        .compression_level(options.compression_level)
+        .symlinks(options.symlinks)
+        .exclude(exclude_patterns(project, &options.exclude))
+        .parallel(pool)
+}
+
+/// Adds the `zip_with` files of the project and of the options, except those already archived with the results
+fn add_zip_with<W: Write + Seek>(archive: &mut RecursiveZipWriter<W>, project: &Project, options: &ZipOptions) {
+    let working_directory = Path::new(&project.working_directory);
+    let mut paths = vec![
+        PathBuf::from(&project.log_directory),
+        PathBuf::from(&project.summary_file),
+        working_directory.join("last_running_configuration.ron"),
+        working_directory.join("last_build_configuration.ron"),
+        PathBuf::from("configuration.ron"),
+    ].into_iter().collect::<HashSet<_>>();
+    let base = options.base.as_ref()
+        .map(|base| without_cur_dir(&restore_path(base, &project.aliases)));
+
+    let files_to_add = project.zip_with.iter()
+        .map(PathBuf::from)
+        .chain(options.zip_with.iter().cloned());
+    for file_to_add in files_to_add {
+        let pattern = restore_path(&file_to_add, &project.aliases);
+        for (full_path, zip_name) in expand_glob(&pattern) {
+            let already_added = paths.iter()
+                .any(|it| without_cur_dir(&full_path).starts_with(without_cur_dir(it)));
+            if !already_added {
+                let added = match &base {
+                    Some(base) if without_cur_dir(&full_path).starts_with(base) => archive.add_path(&without_cur_dir(&full_path), base),
+                    _ => archive.add_path_renamed(&full_path, &zip_name),
+                };
+                match added {
+                    Ok(_) => { paths.insert(full_path); }
+                    Err(e) => warn!("Skipping {}: {}", full_path.display(), e),
+                }
+            }
+        }
+    }
+}
+
+fn finish_archive<W: Write + Seek>(mut archive: RecursiveZipWriter<W>, zip_path: &Path, options: &ZipOptions) -> Result<(), String> {
+    archive.finish()
+        .map_err(|e| format!("Cannot build the zip archive {}: {}", zip_path.display(), e))?;
+
+    let manifest = ZipManifest::read(zip_path)
+        .map_err(|e| format!("Cannot read back the zip archive {}: {}", zip_path.display(), e))?;
+    eprintln!("{}", manifest);
+    if options.verify {
+        ZipManifest::verify(zip_path)
+            .map_err(|e| format!("The zip archive {} is corrupted: {}", zip_path.display(), e))?;
+        info!("The zip archive has been verified");
+    }
+    info!("Results saved in {}", zip_path.display());
+    Ok(())
+}
+
+/// Adds `path` as `zip_name`, warning when it does not exist or cannot be read
+fn add_optional<W: Write + Seek>(archive: &mut RecursiveZipWriter<W>, path: &Path, zip_name: &Path, what: &str) {
+    if !path.exists() {
+        warn!("There is no {} yet ({}), it is not added to the zip archive", what, path.display());
+    } else if let Err(e) = archive.add_path_renamed(path, zip_name) {
+        warn!("Cannot add the {} {} to the zip archive: {}", what, path.display(), e);
+    }
+}
+
+/// Inverse of `zip_project`: restores the configuration, the summary file and the logs in `directory`
+/// with the layout of a regular project.
+pub fn extract_project(zip_path: &Path, project: &Project, directory: &Path) -> Result<(), String> {
+    let name = project.summary_file.trim_end_matches(".csv");
+    let configuration = directory.join(format!("{}.ron", name));
+    let layout = Layout::default();
+    let working_directory = PathBuf::from(working_directory(&configuration, &project.versioning, &layout));
+    let log_directory = PathBuf::from(log_directory(&configuration, &project.versioning, &layout));
+    let summary_file = PathBuf::from(summary_file(&configuration, &project.versioning, &layout, false));
+
+    if configuration.exists() || working_directory.exists() {
+        return Err(format!("Cannot extract the archive, {} or {} already exists", configuration.display(), working_directory.display()));
+    }
+
+    let zip_file = File::open(zip_path)
+        .map_err(|e| format!("Cannot open the zip archive {}: {}", zip_path.display(), e))?;
+    let mut archive = zip::ZipArchive::new(zip_file)
+        .map_err(|e| format!("Cannot read the zip archive {}: {}", zip_path.display(), e))?;
+    // Restored once every file is written, so that no file is written through a link of the archive
+    #[cfg(unix)]
+    let mut links = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)
+            .map_err(|e| format!("Cannot read the zip archive {}: {}", zip_path.display(), e))?;
+        let entry_path = match entry.enclosed_name() {
+            Some(entry_path) => entry_path.to_owned(),
+            None => {
+                warn!("Skipping {}, it is outside of the archive", entry.name());
+                continue;
+            }
+        };
+
+        let target = if entry_path == Path::new("configuration.ron") {
+            configuration.clone()
+        } else if entry_path == Path::new(&project.summary_file) {
+            summary_file.clone()
+        } else if let Ok(log) = entry_path.strip_prefix("logs") {
+            log_directory.join(log)
+        } else {
+            working_directory.join(&entry_path)
+        };
+
+        if entry.is_dir() {
+            fs::create_dir_all(&target)
+                .map_err(|e| format!("Cannot create {}: {}", target.display(), e))?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Cannot create {}: {}", parent.display(), e))?;
+        }
+        #[cfg(unix)]
+        if entry.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000) {
+            let mut link = String::new();
+            entry.read_to_string(&mut link)
+                .map_err(|e| format!("Cannot extract {}: {}", entry.name(), e))?;
+            links.push((target, link));
+            continue;
+        }
+        let mut file = File::create(&target)
+            .map_err(|e| format!("Cannot create {}: {}", target.display(), e))?;
+        std::io::copy(&mut entry, &mut file)
+            .map_err(|e| format!("Cannot extract {}: {}", entry.name(), e))?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            if let Err(e) = fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o777)) {
+                warn!("Cannot restore the permissions of {}: {}", target.display(), e);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    for (target, link) in links {
+        if !stays_within(directory, &target, Path::new(&link)) {
+            warn!("Skipping the symbolic link {} to {}, it points outside of the extracted results", target.display(), link);
+        } else if goes_through_link(directory, &target) {
+            warn!("Skipping the symbolic link {}, its path goes through another link", target.display());
+        } else if let Err(e) = std::os::unix::fs::symlink(&link, &target) {
+            warn!("Cannot restore the symbolic link {} to {}: {}", target.display(), link, e);
+        }
+    }
+
+    info!("Results extracted, the configuration file is {}", configuration.display());
+    Ok(())
+}
+
+/// Whether the relative `link` placed at `path` points under `directory`, judging by the names only
+#[cfg(unix)]
+fn stays_within(directory: &Path, path: &Path, link: &Path) -> bool {
+    let relative = match path.parent().and_then(|it| it.strip_prefix(directory).ok()) {
+        Some(relative) => relative.join(link),
+        None => return false,
+    };
+    let mut depth = 0usize;
+    for component in relative.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Whether one of the directories between `directory` and `path` is a symbolic link
+#[cfg(unix)]
+fn goes_through_link(directory: &Path, path: &Path) -> bool {
+    let relative = path.parent().and_then(|it| it.strip_prefix(directory).ok()).unwrap_or(Path::new(""));
+    let mut ancestor = directory.to_owned();
+    relative.components().any(|component| {
+        ancestor.push(component);
+        fs::symlink_metadata(&ancestor).is_ok_and(|it| it.file_type().is_symlink())
+    })
+}
+
+/// Lists the existing paths matching `pattern` with their name in the zip archive, warning when there is none.
+/// Matches keep their path relative to the last directory of the pattern without wildcard,
+/// so that `{LOGS}/*/*.err` is stored as `logs/<computation>/<file>.err`.
+fn expand_glob(pattern: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let base = without_cur_dir(pattern).components()
+        .take_while(|it| !it.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect::<PathBuf>();
+    let pattern = pattern.to_string_lossy();
+    let matches = match glob::glob(&pattern) {
+        Ok(paths) => paths.filter_map(Result::ok)
+            .map(|path| {
+                let relative = path.strip_prefix(&base).unwrap_or(&path);
+                let relative = without_cur_dir(relative);
+                let zip_name = match base.file_name() {
+                    Some(name) if relative.as_os_str().is_empty() => PathBuf::from(name),
+                    Some(name) => Path::new(name).join(relative),
+                    None => relative.to_path_buf(),
+                };
+                (path, zip_name)
+            })
+            .collect::<Vec<_>>(),
+        Err(e) => {
+            error!("Invalid pattern {}: {}", pattern, e);
+            return Vec::new();
+        }
+    };
+    if matches.is_empty() {
+        warn!("Nothing matches {}, it is not added to the zip archive", pattern);
+    }
+    matches
+}
+
+fn exclude_patterns(project: &Project, cli_patterns: &[String]) -> Vec<glob::Pattern> {
+    project.exclude.iter()
+        .chain(cli_patterns)
+        .filter_map(|pattern| {
+            let pattern = restore_path(Path::new(pattern), &project.aliases);
+            let pattern = without_cur_dir(&pattern);
+            glob::Pattern::new(&pattern.to_string_lossy())
+                .map_err(|e| warn!("Ignoring the invalid exclude pattern {}: {}", pattern.display(), e))
+                .ok()
+        })
+        .collect()
+}
+
+fn without_cur_dir(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|it| *it != Component::CurDir)
+        .collect()
+}
+
+pub fn read_zip_entry(zip_path: &Path, name: &str) -> Option<String> {
+    let mut archive = zip::ZipArchive::new(File::open(zip_path).ok()?).ok()?;
+    let mut content = String::new();
+    archive.by_name(name).ok()?
+        .read_to_string(&mut content)
+        .ok()?;
+    Some(content)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use zip::write::FileOptions;
+
+    #[test]
+    fn links_of_an_archive_never_lead_outside_of_the_extracted_results() {
+        let root = std::env::temp_dir().join(format!("whitesmith-extract-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let outside = root.join("outside");
+        let directory = root.join("extracted");
+        fs::create_dir_all(&outside).unwrap();
+        fs::create_dir_all(&directory).unwrap();
+
+        let zip_path = root.join("crafted.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&zip_path).unwrap());
+        zip.add_symlink("logs/absolute", outside.to_str().unwrap(), FileOptions::default()).unwrap();
+        zip.start_file("logs/absolute/.bashrc", FileOptions::default()).unwrap();
+        zip.write_all(b"written through the link").unwrap();
+        zip.add_symlink("logs/escaping", "../../../outside", FileOptions::default()).unwrap();
+        zip.add_symlink("logs/inside", "../p.csv", FileOptions::default()).unwrap();
+        zip.start_file("p.csv", FileOptions::default()).unwrap();
+        zip.write_all(b"name").unwrap();
+        zip.finish().unwrap();
+
+        let mut project = ron::de::from_str::<Project>(
+            r#"(version: (0, 6, 2), versioning: (url: ""), commands: (build: ""), experiments: [])"#
+        ).unwrap();
+        project.summary_file = String::from("p.csv");
+        extract_project(&zip_path, &project, &directory).unwrap();
+
+        let logs = directory.join("p.d/logs");
+        assert!(!outside.join(".bashrc").exists());
+        assert_eq!(fs::read_to_string(logs.join("absolute/.bashrc")).unwrap(), "written through the link");
+        assert!(!fs::symlink_metadata(logs.join("absolute")).unwrap().file_type().is_symlink());
+        assert!(fs::symlink_metadata(logs.join("escaping")).is_err());
+        assert_eq!(fs::read_to_string(logs.join("inside")).unwrap(), "name");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
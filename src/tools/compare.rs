@@ -0,0 +1,160 @@
+use std::collections::{BTreeSet, HashMap};
+use colored::{ColoredString, Colorize};
+use log::warn;
+use crate::tools::summary::{ColumnKind, SummaryTable};
+
+/// A computation, or one of its iterations, as written in a summary
+struct Row {
+    status: String,
+    /// In seconds
+    time: Option<f64>,
+}
+
+impl Row {
+    fn is_ok(&self) -> bool {
+        self.status == "Ok"
+    }
+}
+
+type Key = (String, String);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verdict {
+    /// Used to succeed and does not anymore
+    Regression,
+    Slower,
+    Fixed,
+    Faster,
+    New,
+    Missing,
+}
+
+impl Verdict {
+    fn is_regression(&self) -> bool {
+        matches!(self, Verdict::Regression | Verdict::Slower)
+    }
+
+    fn colored(&self) -> ColoredString {
+        match self {
+            Verdict::Regression => "regression".red().bold(),
+            Verdict::Slower => "slower".red(),
+            Verdict::Fixed => "fixed".green(),
+            Verdict::Faster => "faster".green(),
+            Verdict::New => "new".normal(),
+            Verdict::Missing => "missing".yellow(),
+        }
+    }
+}
+
+/// Rows of a summary by name and iteration, in the order of the file.
+/// The last row wins when a computation was run several times.
+fn parse(summary: &str) -> Result<(Vec<Key>, HashMap<Key, Row>), String> {
+    let table = SummaryTable::parse(summary).ok_or("The summary is empty")?;
+    if let Some(column) = ["name", "status", "time", "iteration"].iter().find(|it| !table.header.iter().any(|column| column == *it)) {
+        return Err(format!("The summary has no {} column", column));
+    }
+
+    let mut keys = Vec::new();
+    let mut rows = HashMap::new();
+    for cells in &table.rows {
+        let cell = |column: &str| table.cell(cells, column).unwrap_or_default();
+        let key = (cell("name").to_owned(), cell("iteration").to_owned());
+        let row = Row {
+            status: cell("status").to_owned(),
+            time: ColumnKind::Duration.parse(cell("time")).or_else(|| ColumnKind::Number.parse(cell("time"))),
+        };
+        if rows.insert(key.clone(), row).is_none() {
+            keys.push(key);
+        }
+    }
+    Ok((keys, rows))
+}
+
+/// Measure modes of the rows of a summary, empty for the summaries that predate them
+fn measure_modes(summary: &str) -> String {
+    SummaryTable::parse(summary)
+        .map(|table| table.rows.iter()
+            .filter_map(|row| table.cell(row, "measure_mode"))
+            .filter(|it| !it.is_empty())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>()
+            .join(", "))
+        .unwrap_or_default()
+}
+
+fn verdict(before: Option<&Row>, after: Option<&Row>, change: Option<f64>, threshold: f64) -> Option<Verdict> {
+    match (before, after) {
+        (None, _) => Some(Verdict::New),
+        (_, None) => Some(Verdict::Missing),
+        (Some(before), Some(after)) if before.is_ok() && !after.is_ok() => Some(Verdict::Regression),
+        (Some(before), Some(after)) if !before.is_ok() && after.is_ok() => Some(Verdict::Fixed),
+        _ => match change {
+            Some(change) if change >= threshold => Some(Verdict::Slower),
+            Some(change) if change <= -threshold => Some(Verdict::Faster),
+            _ => None,
+        },
+    }
+}
+
+/// Prints the differences between two summaries, matching the rows by computation name and iteration.
+/// Returns whether a computation regressed: it does not succeed anymore, or it is `threshold` percent slower.
+pub fn print_comparison(baseline: &str, other: &str, threshold: f64) -> Result<bool, String> {
+    let (baseline_modes, other_modes) = (measure_modes(baseline), measure_modes(other));
+    if !baseline_modes.is_empty() && !other_modes.is_empty() && baseline_modes != other_modes {
+        warn!("The summaries were not produced in the same measure mode ({} and {}), their times may not be comparable", baseline_modes, other_modes);
+    }
+    let (mut keys, baseline) = parse(baseline)?;
+    let (other_keys, other) = parse(other)?;
+    keys.extend(other_keys.into_iter().filter(|key| !baseline.contains_key(key)));
+
+    let format_time = |row: Option<&Row>| row.and_then(|it| it.time)
+        .map(|it| ColumnKind::Duration.format(it))
+        .unwrap_or_else(|| String::from("-"));
+
+    let mut table = vec![
+        ["name", "iteration", "status", "before", "after", "delta"].map(String::from).to_vec()
+    ];
+    let mut verdicts = vec![None];
+    for key in &keys {
+        let (before, after) = (baseline.get(key), other.get(key));
+        let status = match (before, after) {
+            (Some(before), Some(after)) if before.status == after.status => after.status.clone(),
+            _ => format!(
+                "{} -> {}",
+                before.map(|it| it.status.as_str()).unwrap_or("-"),
+                after.map(|it| it.status.as_str()).unwrap_or("-"),
+            ),
+        };
+        let (delta, change) = match (before.and_then(|it| it.time), after.and_then(|it| it.time)) {
+            (Some(before), Some(after)) if before > 0.0 => {
+                let change = (after - before) / before * 100.0;
+                let sign = if after >= before { "+" } else { "-" };
+                (format!("{}{} ({:+.1}%)", sign, ColumnKind::Duration.format((after - before).abs()), change), Some(change))
+            }
+            _ => (String::from("-"), None),
+        };
+
+        table.push(vec![key.0.clone(), key.1.clone(), status, format_time(before), format_time(after), delta]);
+        verdicts.push(verdict(before, after, change, threshold));
+    }
+
+    let col_sizes = (0..table[0].len())
+        .map(|i| table.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+    for (row, verdict) in table.iter().zip(&verdicts) {
+        for (i, cell) in row.iter().enumerate() {
+            eprint!("{:1$}", cell, col_sizes[i] + 3);
+        }
+        match verdict {
+            Some(verdict) => eprintln!("{}", verdict.colored()),
+            None => eprintln!(),
+        }
+    }
+
+    let regressions = verdicts.iter().flatten().filter(|it| it.is_regression()).count();
+    if regressions > 0 {
+        eprintln!("\n{} regression(s) over {} computation(s)", regressions, keys.len());
+    }
+    Ok(regressions > 0)
+}
@@ -0,0 +1,68 @@
+use std::fmt::Write as _;
+use crate::tools::hostname;
+use crate::tools::summary::{ColumnKind, SummaryTable};
+
+fn escape(value: &str) -> String {
+    value.chars()
+        .map(|it| match it {
+            '&' => String::from("&amp;"),
+            '<' => String::from("&lt;"),
+            '>' => String::from("&gt;"),
+            '"' => String::from("&quot;"),
+            '\'' => String::from("&apos;"),
+            _ => it.to_string(),
+        })
+        .collect()
+}
+
+/// JUnit XML report of a summary: a `<testsuite>` named `suite` with a `<testcase>` per row.
+/// An `Error` (or `BeforeError`) is a `<failure>`, a `Timeout` an `<error>` and a `Skipped` computation is `<skipped>`.
+pub fn junit_report(suite: &str, summary: &str) -> Result<String, String> {
+    let table = SummaryTable::parse(summary).ok_or("The summary is empty")?;
+
+    let mut cases = String::new();
+    let (mut failures, mut errors, mut skipped, mut total_time) = (0, 0, 0, 0.0);
+    for row in &table.rows {
+        let cell = |column: &str| table.cell(row, column).unwrap_or_default();
+        let status = cell("status");
+        let time = ColumnKind::Duration.parse(cell("time"))
+            .or_else(|| ColumnKind::Number.parse(cell("time")))
+            .unwrap_or_default();
+        total_time += time;
+
+        let mut name = cell("name").to_owned();
+        if !matches!(cell("iteration"), "" | "1/1") {
+            name = format!("{} ({})", name, cell("iteration"));
+        }
+        let _ = write!(cases, "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\"", escape(&name), escape(suite), time);
+        let message = match cell("log") {
+            "" => status.to_owned(),
+            log => format!("{}, see {}", status, log),
+        };
+        match status {
+            "Ok" => cases.push_str("/>\n"),
+            "Skipped" => {
+                skipped += 1;
+                cases.push_str(">\n      <skipped/>\n    </testcase>\n");
+            }
+            "Timeout" => {
+                errors += 1;
+                let _ = write!(cases, ">\n      <error type=\"Timeout\" message=\"{}\"/>\n    </testcase>\n", escape(&message));
+            }
+            _ => {
+                failures += 1;
+                let _ = write!(cases, ">\n      <failure type=\"{}\" message=\"{}\"/>\n    </testcase>\n", escape(status), escape(&message));
+            }
+        }
+    }
+
+    let mut report = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        report,
+        "<testsuites tests=\"{0}\" failures=\"{1}\" errors=\"{2}\" time=\"{3:.3}\">\n  <testsuite name=\"{4}\" tests=\"{0}\" failures=\"{1}\" errors=\"{2}\" skipped=\"{6}\" time=\"{3:.3}\" hostname=\"{5}\">",
+        table.rows.len(), failures, errors, total_time, escape(suite), escape(&hostname()), skipped,
+    );
+    report.push_str(&cases);
+    report.push_str("  </testsuite>\n</testsuites>\n");
+    Ok(report)
+}
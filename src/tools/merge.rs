@@ -0,0 +1,146 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use log::warn;
+use zip::{ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+use crate::tools::sanitize_file_name;
+use crate::tools::summary::SummaryTable;
+
+/// What happens when several archives hold results for the same computation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Keep the results of the last archive
+    Last,
+    /// Refuse to merge
+    Error,
+}
+
+/// A results archive and the name of its summary
+pub struct Archive {
+    pub path: PathBuf,
+    pub summary: String,
+}
+
+fn read_summary(archive: &mut ZipArchive<File>, name: &str) -> Result<SummaryTable, String> {
+    let mut content = String::new();
+    archive.by_name(name)
+        .map_err(|e| format!("no summary {}: {}", name, e))?
+        .read_to_string(&mut content)
+        .map_err(|e| format!("cannot read the summary {}: {}", name, e))?;
+    SummaryTable::parse(&content).ok_or_else(|| format!("the summary {} is empty", name))
+}
+
+/// Directories of the logs of the computations, relative to the log directory
+fn log_directories(summary: &SummaryTable, names: &HashSet<&str>) -> HashSet<String> {
+    let mut directories = HashSet::new();
+    for row in &summary.rows {
+        let name = summary.cell(row, "name").unwrap_or_default();
+        if names.contains(name) {
+            directories.insert(sanitize_file_name(name));
+            if let Some((directory, _)) = summary.cell(row, "log").and_then(|it| it.split_once('/')) {
+                directories.insert(directory.to_owned());
+            }
+        }
+    }
+    directories
+}
+
+/// Concatenates the summaries of `archives` into `output`, a summary file (`.csv`) or an archive
+/// holding the configuration of the first archive and, `with_logs`, the logs of the merged computations.
+/// Returns the number of rows written.
+pub fn merge(archives: &[Archive], output: &Path, output_summary: &str, with_logs: bool, on_conflict: OnConflict) -> Result<usize, String> {
+    let mut zips = Vec::new();
+    let mut summaries = Vec::new();
+    for archive in archives {
+        let file = File::open(&archive.path).map_err(|e| format!("Cannot open {}: {}", archive.path.display(), e))?;
+        let mut zip = ZipArchive::new(file).map_err(|e| format!("Cannot read {}: {}", archive.path.display(), e))?;
+        let summary = read_summary(&mut zip, &archive.summary).map_err(|e| format!("{}: {}", archive.path.display(), e))?;
+        zips.push(zip);
+        summaries.push(summary);
+    }
+
+    // The computations are owned by the last archive that ran them
+    let mut owners = HashMap::new();
+    let mut conflicts = Vec::new();
+    for (index, summary) in summaries.iter().enumerate() {
+        let names = summary.rows.iter()
+            .filter_map(|row| summary.cell(row, "name"))
+            .collect::<HashSet<_>>();
+        for name in names {
+            if let Some(previous) = owners.insert(name.to_owned(), index) {
+                conflicts.push(format!("{} ({} and {})", name, archives[previous].path.display(), archives[index].path.display()));
+            }
+        }
+    }
+    if on_conflict == OnConflict::Error && !conflicts.is_empty() {
+        conflicts.sort();
+        return Err(format!("Several archives hold results for {}", conflicts.join(", ")));
+    }
+    for conflict in &conflicts {
+        warn!("Several archives hold results for {}, the ones of the last archive are kept", conflict);
+    }
+
+    let mut header = Vec::<String>::new();
+    for summary in &summaries {
+        for column in &summary.header {
+            if !header.contains(column) {
+                header.push(column.clone());
+            }
+        }
+    }
+
+    let mut content = header.join("\t") + "\n";
+    let mut nb_rows = 0;
+    for (index, summary) in summaries.iter().enumerate() {
+        for row in &summary.rows {
+            let name = summary.cell(row, "name").unwrap_or_default();
+            if owners.get(name) != Some(&index) {
+                continue;
+            }
+            let cells = header.iter()
+                .map(|column| summary.cell(row, column).unwrap_or_default())
+                .collect::<Vec<_>>();
+            content.push_str(&cells.join("\t"));
+            content.push('\n');
+            nb_rows += 1;
+        }
+    }
+
+    if output.extension().is_some_and(|it| it == "csv") {
+        fs::write(output, content).map_err(|e| format!("Cannot write {}: {}", output.display(), e))?;
+        return Ok(nb_rows);
+    }
+
+    let write_error = |e: zip::result::ZipError| format!("Cannot write {}: {}", output.display(), e);
+    let file = File::create(output).map_err(|e| format!("Cannot create {}: {}", output.display(), e))?;
+    let mut writer = ZipWriter::new(file);
+    if let Ok(configuration) = zips[0].by_name("configuration.ron") {
+        writer.raw_copy_file(configuration).map_err(write_error)?;
+    }
+    writer.start_file(output_summary, FileOptions::default()).map_err(write_error)?;
+    writer.write_all(content.as_bytes()).map_err(|e| write_error(e.into()))?;
+
+    if with_logs {
+        for (index, (zip, summary)) in zips.iter_mut().zip(&summaries).enumerate() {
+            let names = owners.iter()
+                .filter(|(_, owner)| **owner == index)
+                .map(|(name, _)| name.as_str())
+                .collect::<HashSet<_>>();
+            let directories = log_directories(summary, &names);
+            for i in 0..zip.len() {
+                let entry = zip.by_index_raw(i).map_err(|e| format!("Cannot read {}: {}", archives[index].path.display(), e))?;
+                let owned = entry.name().strip_prefix("logs/")
+                    .and_then(|it| it.split_once('/'))
+                    .is_some_and(|(directory, _)| directories.contains(directory));
+                if owned {
+                    writer.raw_copy_file(entry).map_err(write_error)?;
+                }
+            }
+        }
+    }
+    writer.finish().map_err(write_error)?;
+    Ok(nb_rows)
+}
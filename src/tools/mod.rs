@@ -1,57 +1,394 @@
-use zip::{ZipWriter, CompressionMethod};
+pub mod affinity;
+pub mod archive;
+pub mod compare;
+pub mod merge;
+pub mod junit;
+pub mod notes;
+pub mod notify;
+pub mod prometheus;
+pub mod summary;
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::mpsc::{self, Receiver};
+use zip::{ZipArchive, ZipWriter, CompressionMethod};
 use zip::write::FileOptions;
 use zip::result::ZipResult;
 use zip::result::ZipError;
-use std::io::Write;
+use std::io::{self, BufReader, Cursor, ErrorKind, Read, Write};
 use std::io::Seek;
-use std::path::Path;
-use std::fs::{File};
+use std::path::{Component, Path, PathBuf};
+use std::fs::{self, File};
+use std::fmt::{Display, Formatter};
+use glob::Pattern;
+use bytesize::ByteSize;
+use log::{debug, warn};
+use threadpool::ThreadPool;
+use crate::tools::archive::Symlinks;
+
+#[cfg(unix)]
+pub fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result != 0 {
+        return String::from("unknown");
+    }
+    let len = buffer.iter().position(|&it| it == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_else(|_| String::from("unknown"))
+}
+
+/// Number of logical CPUs available to whitesmith, 1 when it cannot be known
+pub fn available_cores() -> usize {
+    std::thread::available_parallelism().map(usize::from).unwrap_or(1)
+}
+
+/// Replaces the characters that are unsafe in a file name (separators, spaces, shell metacharacters...) by `_`,
+/// so that a computation name can be used as a path component.
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized = name.chars()
+        .map(|it| if it.is_alphanumeric() || "-_.=+,".contains(it) { it } else { '_' })
+        .collect::<String>();
+    if sanitized.is_empty() || sanitized.chars().all(|it| it == '.') {
+        sanitized.replace('.', "_") + "_"
+    } else {
+        sanitized
+    }
+}
 
+/// Size of the chunks in which the content of a file is copied to the archive
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Largest file compressed in memory on the pool, the larger ones are streamed to the archive one at a time
+const MAX_PARALLEL_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Writes a zip archive entry by entry. `add_path`, `add_path_renamed` and `add_reader` stream the content
+/// in chunks, so a multi-GB log never sits in memory. `add_buf` takes content that is already in memory.
+/// With a thread pool (see `parallel`), the files up to `MAX_PARALLEL_FILE_SIZE` are compressed in memory on the pool instead.
 pub struct RecursiveZipWriter<W: Write + Seek> {
     zip_writer: ZipWriter<W>,
     options: FileOptions,
+    exclude: Vec<Pattern>,
+    symlinks: Symlinks,
+    /// Names of the entries already in the archive, a second entry with the same name is skipped
+    entries: HashSet<String>,
+    pool: Option<ThreadPool>,
+    /// Entries being compressed on the pool, written in this order
+    pending: VecDeque<(String, Receiver<ZipResult<Vec<u8>>>)>,
+}
+
+impl<W: Read + Write + Seek> RecursiveZipWriter<W> {
+    /// Opens an existing archive to add entries after the ones it holds, which are not compressed again
+    pub fn new_append(mut inner: W) -> ZipResult<Self> {
+        let entries = ZipArchive::new(&mut inner)?.file_names().map(String::from).collect();
+        let zip_writer = ZipWriter::new_append(inner)?;
+        Ok(RecursiveZipWriter {
+            zip_writer,
+            options: FileOptions::default(),
+            exclude: Vec::new(),
+            symlinks: Symlinks::default(),
+            entries,
+            pool: None,
+            pending: VecDeque::new(),
+        })
+    }
 }
 
 impl<W: Write + Seek> RecursiveZipWriter<W> {
     pub fn new(inner: W) -> Self {
-        RecursiveZipWriter { zip_writer: ZipWriter::new(inner), options: FileOptions::default() }
+        RecursiveZipWriter {
+            zip_writer: ZipWriter::new(inner),
+            options: FileOptions::default(),
+            exclude: Vec::new(),
+            symlinks: Symlinks::default(),
+            entries: HashSet::new(),
+            pool: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Name of the entry `zip_path`, or None when the archive already has one with this name
+    fn claim_entry(&mut self, zip_path: &Path) -> Option<String> {
+        let name = entry_name(zip_path);
+        if !self.entries.insert(name.clone()) {
+            warn!("{} is already in the archive, it is not added again", name);
+            return None;
+        }
+        Some(name)
+    }
+
+    /// Starts the entry `zip_path`, returns false when the archive already has one with this name
+    fn start_entry(&mut self, zip_path: &Path, options: FileOptions) -> Result<bool, ZipError> {
+        self.write_pending(0)?;
+        match self.claim_entry(zip_path) {
+            Some(name) => {
+                self.zip_writer.start_file(name, options)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn is_excluded(&self, real_path: &Path) -> bool {
+        let real_path = real_path.strip_prefix(".").unwrap_or(real_path);
+        self.exclude.iter().any(|pattern| {
+            pattern.matches_path(real_path)
+                || real_path.file_name().is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+        })
     }
 
+    /// Adds a file, or a directory with its content, as `zip_path`.
+    /// Fails with `ErrorKind::NotFound` when `real_path` does not exist. An entry of a directory that cannot be
+    /// added is skipped with a warning.
     pub fn add_path_renamed(&mut self, real_path: &Path, zip_path: &Path) -> Result<(), ZipError> {
+        self.add_path_within(real_path, zip_path, &mut Vec::new())
+    }
+
+    /// `ancestors` are the directories being added, to stop at a symbolic link to one of them
+    fn add_path_within(&mut self, real_path: &Path, zip_path: &Path, ancestors: &mut Vec<PathBuf>) -> Result<(), ZipError> {
+        if self.is_excluded(real_path) {
+            return Ok(());
+        }
+        let metadata = fs::symlink_metadata(real_path).map_err(|e| match e.kind() {
+            ErrorKind::NotFound => io::Error::new(ErrorKind::NotFound, format!("{} does not exist", real_path.display())),
+            _ => e,
+        })?;
+        if metadata.file_type().is_symlink() {
+            match self.symlinks {
+                Symlinks::Skip => {
+                    debug!("Skipping the symbolic link {}", real_path.display());
+                    return Ok(());
+                }
+                Symlinks::Store => {
+                    let target = fs::read_link(real_path)?;
+                    self.write_pending(0)?;
+                    if let Some(name) = self.claim_entry(zip_path) {
+                        self.zip_writer.add_symlink(name, target.to_string_lossy(), self.options)?;
+                    }
+                    return Ok(());
+                }
+                Symlinks::Follow if !real_path.exists() => {
+                    warn!("Skipping the dangling symbolic link {} to {}", real_path.display(),
+                          fs::read_link(real_path).unwrap_or_default().display());
+                    return Ok(());
+                }
+                Symlinks::Follow => {}
+            }
+        }
         if real_path.is_file() {
-            self.zip_writer
-                .start_file(zip_path.to_string_lossy().into_owned(), self.options)?;
-            let mut file = File::open(real_path).unwrap();
-            std::io::copy(&mut file, &mut self.zip_writer)?;
+            let file = File::open(real_path)?;
+            // Keeps the scripts and binaries of `zip_with` executable once extracted
+            let options = with_permissions(self.options, &file);
+            let size = file.metadata()?.len();
+            match self.pool.clone() {
+                Some(pool) if size <= MAX_PARALLEL_FILE_SIZE => {
+                    if let Some(name) = self.claim_entry(zip_path) {
+                        let (sender, receiver) = mpsc::channel();
+                        let entry = name.clone();
+                        pool.execute(move || {
+                            let _ = sender.send(compress(file, entry, options));
+                        });
+                        self.pending.push_back((name, receiver));
+                        // Bounds the memory held by the entries compressed ahead of the one being written
+                        self.write_pending(2 * pool.max_count())?;
+                    }
+                }
+                _ => self.copy_entry(BufReader::with_capacity(COPY_BUFFER_SIZE, file), zip_path, options)?,
+            }
         } else if real_path.is_dir() {
-            for listing in real_path.read_dir().unwrap() {
-                let file_name = listing.unwrap().file_name();
-                self.add_path_renamed(&real_path.join(&file_name), &zip_path.join(&file_name))
-                    .unwrap_or(());
+            let canonical = real_path.canonicalize()?;
+            if ancestors.contains(&canonical) {
+                warn!("Skipping {}, it links to a directory that contains it", real_path.display());
+                return Ok(());
+            }
+            ancestors.push(canonical);
+            for listing in real_path.read_dir()? {
+                let file_name = listing?.file_name();
+                let entry = real_path.join(&file_name);
+                if let Err(e) = self.add_path_within(&entry, &zip_path.join(&file_name), ancestors) {
+                    warn!("Skipping {}: {}", entry.display(), e);
+                }
             }
+            ancestors.pop();
         } else {
-            println!("Cannot add {:?} to the current archive", real_path);
+            warn!("Skipping {}, it is neither a file nor a directory", real_path.display());
         }
         Ok(())
     }
 
+    /// Streams `reader` to the entry `zip_path` until its end
+    pub fn add_reader<R: Read>(&mut self, reader: R, zip_path: &Path) -> Result<(), ZipError> {
+        self.copy_entry(reader, zip_path, self.options)
+    }
+
+    fn copy_entry<R: Read>(&mut self, mut reader: R, zip_path: &Path, options: FileOptions) -> Result<(), ZipError> {
+        if self.start_entry(zip_path, options)? {
+            io::copy(&mut reader, &mut self.zip_writer)?;
+        }
+        Ok(())
+    }
+
+    /// Adds `buf` as `zip_path`, for small generated content such as the configuration. Prefer `add_reader` for
+    /// content that does not already sit in memory
     pub fn add_buf(&mut self, buf: &[u8], zip_path: &Path) -> Result<(), ZipError> {
-        self.zip_writer
-            .start_file(zip_path.to_string_lossy().into_owned(), self.options)?;
-        self.zip_writer.write_all(buf)?;
+        if self.start_entry(zip_path, self.options)? {
+            self.zip_writer.write_all(buf)?;
+        }
         Ok(())
     }
 
-    pub fn add_path(&mut self, real_path: &Path) -> Result<(), ZipError> {
-        self.add_path_renamed(real_path, &Path::new(real_path.file_name().unwrap()))
+    /// Adds `real_path` under its path relative to `base`, or under its file name when it is outside of `base`
+    pub fn add_path(&mut self, real_path: &Path, base: &Path) -> Result<(), ZipError> {
+        let zip_path = real_path.strip_prefix(base).ok()
+            .filter(|it| !it.as_os_str().is_empty())
+            .or_else(|| real_path.file_name().map(Path::new))
+            .unwrap_or(real_path);
+        self.add_path_renamed(real_path, zip_path)
+    }
+
+    /// Writes the entries compressed on the pool, in the order they were added, until at most `remaining` are left.
+    /// An entry that cannot be compressed is skipped with a warning.
+    fn write_pending(&mut self, remaining: usize) -> Result<(), ZipError> {
+        while self.pending.len() > remaining {
+            let (name, receiver) = self.pending.pop_front().unwrap();
+            let compressed = receiver.recv()
+                .unwrap_or_else(|_| Err(ZipError::Io(io::Error::other("the compression thread panicked"))));
+            match compressed {
+                Ok(compressed) => {
+                    // Not compressed again. zip 0.6 keeps the permissions but drops the regular file type bits,
+                    // the entry is still extracted as a file
+                    let mut archive = ZipArchive::new(Cursor::new(compressed))?;
+                    self.zip_writer.raw_copy_file(archive.by_index_raw(0)?)?;
+                }
+                Err(e) => warn!("Skipping {}: {}", name, e),
+            }
+        }
+        Ok(())
     }
 
     pub fn finish(&mut self) -> ZipResult<W> {
+        self.write_pending(0)?;
         self.zip_writer.finish()
     }
 
-    pub fn compression_method(self, method: CompressionMethod) -> Self {
-        let _ = self.options.compression_method(method);
+    /// Compresses the files on `pool`, up to twice as many as its threads being held in memory at once.
+    /// The files larger than `MAX_PARALLEL_FILE_SIZE` are streamed instead. The entries are still written
+    /// in the order they are added.
+    pub fn parallel(mut self, pool: Option<ThreadPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    pub fn compression_method(mut self, method: CompressionMethod) -> Self {
+        self.options = self.options.compression_method(method);
+        self
+    }
+
+    pub fn compression_level(mut self, level: Option<i32>) -> Self {
+        self.options = self.options.compression_level(level);
+        self
+    }
+
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Files and directories matching one of the patterns are skipped, either by path or by file name
+    pub fn exclude(mut self, patterns: Vec<Pattern>) -> Self {
+        self.exclude = patterns;
         self
     }
-}
\ No newline at end of file
+}
+
+/// A single entry archive holding `file` compressed as `name`
+fn compress(file: File, name: String, options: FileOptions) -> ZipResult<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer.start_file(name, options)?;
+    io::copy(&mut BufReader::with_capacity(COPY_BUFFER_SIZE, file), &mut writer)?;
+    Ok(writer.finish()?.into_inner())
+}
+
+#[cfg(unix)]
+fn with_permissions(options: FileOptions, file: &File) -> FileOptions {
+    use std::os::unix::fs::PermissionsExt;
+    match file.metadata() {
+        Ok(metadata) => options.unix_permissions(metadata.permissions().mode() & 0o777),
+        Err(_) => options,
+    }
+}
+
+#[cfg(not(unix))]
+fn with_permissions(options: FileOptions, _file: &File) -> FileOptions {
+    options
+}
+
+/// Name of the entry stored for `zip_path`: relative, without `.` or `..`, and with `/` separators
+/// so that extracting the archive never writes outside of the target directory.
+fn entry_name(zip_path: &Path) -> String {
+    zip_path.components()
+        .filter_map(|it| match it {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub struct ZipEntry {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+}
+
+/// Entries of a zip archive, as read back from the file.
+pub struct ZipManifest {
+    pub entries: Vec<ZipEntry>,
+}
+
+impl ZipManifest {
+    pub fn read(path: &Path) -> ZipResult<Self> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut entries = Vec::with_capacity(archive.len());
+        for i in 0..archive.len() {
+            let file = archive.by_index_raw(i)?;
+            entries.push(ZipEntry { name: file.name().to_owned(), size: file.size(), compressed_size: file.compressed_size() });
+        }
+        Ok(ZipManifest { entries })
+    }
+
+    /// Reads every entry of the archive to the end, which checks its CRC.
+    pub fn verify(path: &Path) -> ZipResult<()> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut buffer = Vec::new();
+        for i in 0..archive.len() {
+            buffer.clear();
+            archive.by_index(i)?.read_to_end(&mut buffer)?;
+        }
+        Ok(())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.entries.iter().map(|it| it.size).sum()
+    }
+
+    pub fn compressed_size(&self) -> u64 {
+        self.entries.iter().map(|it| it.compressed_size).sum()
+    }
+}
+
+impl Display for ZipManifest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let width = self.entries.iter().map(|it| it.name.len()).max().unwrap_or(0);
+        for entry in &self.entries {
+            writeln!(f, "{:width$}   {:>10}   {:>10}", entry.name, ByteSize(entry.size).to_string(), ByteSize(entry.compressed_size).to_string(), width = width)?;
+        }
+        write!(
+            f, "{} files, {} ({} compressed)",
+            self.entries.len(), ByteSize(self.size()), ByteSize(self.compressed_size())
+        )
+    }
+}
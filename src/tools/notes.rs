@@ -0,0 +1,56 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use termimad::crossterm::style::Color;
+use termimad::MadSkin;
+
+/// Colors used to render the notes in a terminal
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Dark,
+    Light,
+    #[default]
+    /// Guess the background from the `COLORFGBG` variable set by some terminals, dark otherwise
+    Auto,
+}
+
+impl Theme {
+    fn resolve(self) -> Theme {
+        match self {
+            Theme::Auto => match background_color() {
+                // 7 and 15 are the white and bright white colors of the 16 colors palette
+                Some(7) | Some(15) => Theme::Light,
+                _ => Theme::Dark,
+            },
+            theme => theme,
+        }
+    }
+
+    /// Builds the skin of the theme, without any style when `NO_COLOR` is set.
+    pub fn skin(self) -> MadSkin {
+        if std::env::var_os("NO_COLOR").is_some_and(|it| !it.is_empty()) {
+            return MadSkin::no_style();
+        }
+        match self.resolve() {
+            Theme::Light => {
+                let mut skin = MadSkin::default_light();
+                skin.bold.set_fg(Color::DarkRed);
+                skin
+            }
+            _ => {
+                let mut skin = MadSkin::default_dark();
+                skin.bold.set_fg(Color::Red);
+                skin
+            }
+        }
+    }
+}
+
+/// `COLORFGBG` is `<foreground>;<background>`, sometimes with a middle field
+fn background_color() -> Option<u8> {
+    std::env::var("COLORFGBG").ok()?
+        .rsplit(';')
+        .next()?
+        .parse()
+        .ok()
+}
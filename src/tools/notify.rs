@@ -0,0 +1,93 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use clap::ValueEnum;
+use log::{debug, warn};
+use serde_json::json;
+use crate::tools::hostname;
+use crate::tools::prometheus::RunMetrics;
+
+/// Runs after which the `notify_url` webhook is called
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NotifyOn {
+    /// Every computation succeeded
+    Success,
+    /// A computation failed or timed out, or the run was interrupted
+    Failure,
+    #[default]
+    Always,
+}
+
+/// Posts a JSON summary of the run to a webhook. The `text` and `content` fields hold a readable message
+/// for Slack and Discord. A failure only prints a warning.
+pub fn notify(url: &str, notify_on: NotifyOn, metrics: &RunMetrics, aborted: bool) {
+    let failed = aborted || metrics.results.keys().any(|status| status != "Ok" && status != "Skipped");
+    let wanted = match notify_on {
+        NotifyOn::Success => !failed,
+        NotifyOn::Failure => failed,
+        NotifyOn::Always => true,
+    };
+    if !wanted {
+        return;
+    }
+
+    let status = if aborted { "aborted" } else if failed { "failure" } else { "success" };
+    let host = hostname();
+    let counts = metrics.results.iter()
+        .map(|(status, (count, _))| format!("{} {}", count, status))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let text = format!(
+        "whitesmith: {} on {} {} after {} ({})",
+        metrics.project, host, status, humantime::format_duration(Duration::from_millis(metrics.duration.as_millis() as u64)),
+        if counts.is_empty() { String::from("nothing run") } else { counts },
+    );
+    let body = json!({
+        "text": text,
+        "content": text,
+        "project": metrics.project,
+        "host": host,
+        "status": status,
+        "duration_seconds": metrics.duration.as_secs_f64(),
+        "results": metrics.results.iter().map(|(status, (count, _))| (status.clone(), json!(count))).collect::<serde_json::Map<_, _>>(),
+        "computations": metrics.statuses,
+    });
+
+    debug!("Posting {} to {}", body, redacted(url));
+    // curl keeps whitesmith free of an HTTP and TLS stack. The URL of a webhook holds its token, so it is passed
+    // in a configuration read from stdin rather than on the command line, where any local user would see it
+    let config = format!("url = \"{}\"\ndata-binary = \"{}\"\n", quoted(url), quoted(&body.to_string()));
+    let child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", "30", "--output", "/dev/null"])
+        .args(["--header", "Content-Type: application/json", "--config", "-"])
+        .stdin(Stdio::piped())
+        .spawn();
+    let result = child.and_then(|mut child| {
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(config.as_bytes())?;
+        }
+        child.wait()
+    });
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("Cannot notify {}: curl exited with {}", redacted(url), status),
+        Err(e) => warn!("Cannot notify {}: {}", redacted(url), e),
+    }
+}
+
+/// Escapes a value for a double-quoted string of a curl configuration
+fn quoted(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+/// The scheme and the host of `url`, without the credentials, the path and the query that may hold a token
+fn redacted(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    if scheme.is_empty() {
+        format!("{}/...", host)
+    } else {
+        format!("{}://{}/...", scheme, host)
+    }
+}
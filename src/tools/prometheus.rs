@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Outcome of a run, exported in the Prometheus text format
+pub struct RunMetrics {
+    /// Value of the `project` label
+    pub project: String,
+    /// Rows written during the run by status, with the total time of their computations
+    pub results: BTreeMap<String, (u64, Duration)>,
+    /// Computations of the project by status, as shown by `show status`
+    pub statuses: BTreeMap<&'static str, usize>,
+    pub duration: Duration,
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl RunMetrics {
+    pub fn format(&self) -> String {
+        let project = escape(&self.project);
+        let mut text = String::new();
+        let mut gauge = |name: &str, help: &str, values: Vec<(String, String)>| {
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} gauge", name);
+            for (labels, value) in values {
+                let _ = writeln!(text, "{}{{project=\"{}\"{}}} {}", name, project, labels, value);
+            }
+        };
+
+        gauge(
+            "whitesmith_results",
+            "Computations run during the last run, by result",
+            self.results.iter()
+                .map(|(status, (count, _))| (format!(",result=\"{}\"", escape(status)), count.to_string()))
+                .collect(),
+        );
+        gauge(
+            "whitesmith_results_seconds",
+            "Total time of the computations run during the last run, by result",
+            self.results.iter()
+                .map(|(status, (_, time))| (format!(",result=\"{}\"", escape(status)), time.as_secs_f64().to_string()))
+                .collect(),
+        );
+        gauge(
+            "whitesmith_computations",
+            "Computations of the project, by status",
+            self.statuses.iter()
+                .map(|(status, count)| (format!(",status=\"{}\"", status), count.to_string()))
+                .collect(),
+        );
+        gauge(
+            "whitesmith_run_duration_seconds",
+            "Duration of the last run",
+            vec![(String::new(), self.duration.as_secs_f64().to_string())],
+        );
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        gauge(
+            "whitesmith_last_run_timestamp_seconds",
+            "End of the last run, in seconds since the epoch",
+            vec![(String::new(), now.as_secs().to_string())],
+        );
+        text
+    }
+
+    /// Replaces `path` at once, so that a collector never reads a partial file
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, self.format())?;
+        fs::rename(&partial, path)
+    }
+}
@@ -0,0 +1,700 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Error, ErrorKind, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use colored::{ColoredString, Colorize};
+use log::warn;
+
+pub struct SummaryOptions {
+    pub sort_columns: Option<Vec<SortKey>>,
+    pub filters: Vec<Filter>,
+    pub columns: Option<Vec<String>>,
+    pub stats: bool,
+    /// Only keep the first rows once sorted and filtered
+    pub head: Option<usize>,
+    /// Only keep the last rows once sorted and filtered
+    pub tail: Option<usize>,
+    /// Color the status column: green when Ok, yellow on a timeout, cyan when skipped and red on an error
+    pub color: bool,
+    pub widths: ColumnWidths,
+}
+
+/// How the width of the columns is found
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColumnWidths {
+    /// The longest cell of each column, which takes a pass over the rows before printing them
+    #[default]
+    Measured,
+    /// At least this width, the longer cells are not aligned
+    Assumed(usize),
+    /// The longest cell among the first rows only
+    Sampled(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterOperator { Equal, NotEqual, Lower, Greater }
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    pub column: String,
+    pub operator: FilterOperator,
+    pub value: String,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let operators = [
+            ("!=", FilterOperator::NotEqual),
+            ("=", FilterOperator::Equal),
+            ("<", FilterOperator::Lower),
+            (">", FilterOperator::Greater),
+        ];
+        operators.iter()
+            .filter_map(|(symbol, operator)| s.find(symbol).map(|pos| (pos, symbol, operator)))
+            .min_by_key(|(pos, _, _)| *pos)
+            .map(|(pos, symbol, &operator)| Filter {
+                column: s[..pos].trim().to_owned(),
+                operator,
+                value: s[pos + symbol.len()..].trim().to_owned(),
+            })
+            .filter(|it| !it.column.is_empty())
+            .ok_or_else(|| format!("Cannot parse {} as a filter, expected COLUMN=VALUE, COLUMN!=VALUE, COLUMN<VALUE or COLUMN>VALUE", s))
+    }
+}
+
+impl Filter {
+    fn matches(&self, cell: &str) -> bool {
+        let equals = cell.eq_ignore_ascii_case(&self.value)
+            || compare_cells(cell, &self.value) == Ordering::Equal;
+        match self.operator {
+            FilterOperator::Equal => equals,
+            FilterOperator::NotEqual => !equals,
+            FilterOperator::Lower => compare_cells(cell, &self.value) == Ordering::Less,
+            FilterOperator::Greater => compare_cells(cell, &self.value) == Ordering::Greater,
+        }
+    }
+}
+
+/// How the cells of a column are compared when sorting the summary
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortMode {
+    /// Durations by value, then numbers within text (`run2` < `run10`)
+    Human,
+    /// Numbers and durations by value, the other cells after them
+    Numeric,
+    /// Byte by byte, e.g. for commit hashes
+    Lexical,
+}
+
+/// Column of a `--sort`, written `[~]COLUMN[:human|num|lex]`, `~` reversing the order
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub column: String,
+    pub reverse: bool,
+    pub mode: SortMode,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (reverse, s) = match s.strip_prefix('~') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (column, mode) = match s.rsplit_once(':') {
+            Some((column, "human")) => (column, SortMode::Human),
+            Some((column, "num")) => (column, SortMode::Numeric),
+            Some((column, "lex")) => (column, SortMode::Lexical),
+            Some((_, mode)) => return Err(format!("Unknown sort mode '{}', expected human, num or lex", mode)),
+            None => (s, SortMode::Human),
+        };
+        if column.trim().is_empty() {
+            return Err(format!("Cannot parse {} as a sort key, expected [~]COLUMN[:human|num|lex]", s));
+        }
+        Ok(SortKey { column: column.trim().to_owned(), reverse, mode })
+    }
+}
+
+impl SortKey {
+    fn compare(&self, lhs: &str, rhs: &str) -> Ordering {
+        let ordering = match self.mode {
+            SortMode::Human => compare_cells(lhs, rhs),
+            SortMode::Lexical => lhs.cmp(rhs),
+            SortMode::Numeric => {
+                let value = |cell: &str| ColumnKind::Number.parse(cell.trim())
+                    .or_else(|| ColumnKind::Duration.parse(cell.trim()));
+                match (value(lhs), value(rhs)) {
+                    (Some(lhs), Some(rhs)) => lhs.total_cmp(&rhs),
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => lhs.cmp(rhs),
+                }
+            }
+        };
+        if self.reverse { ordering.reverse() } else { ordering }
+    }
+}
+
+/// Rows of a summary file, with the cells of the header first
+pub struct SummaryTable {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl SummaryTable {
+    /// `None` if the summary does not even have a header
+    pub fn parse(content: &str) -> Option<Self> {
+        let mut lines = content.lines()
+            .filter(|it| !it.is_empty())
+            .map(|line| line.split('\t').map(String::from).collect::<Vec<_>>());
+        let header = lines.next()?;
+        Some(SummaryTable { header, rows: lines.collect() })
+    }
+
+    pub fn cell<'a>(&'a self, row: &'a [String], column: &str) -> Option<&'a str> {
+        self.header.iter()
+            .position(|it| it == column)
+            .and_then(|index| row.get(index))
+            .map(String::as_str)
+    }
+}
+
+pub fn print_summary<RS>(reader: &mut BufReader<RS>, options: &SummaryOptions) -> std::io::Result<()>
+    where RS: Read {
+    write_summary(reader, options, &mut BufWriter::new(std::io::stderr().lock()))
+}
+
+fn write_summary<RS: Read, W: Write>(reader: &mut BufReader<RS>, options: &SummaryOptions, out: &mut W) -> std::io::Result<()> {
+    // The cells borrow from the content, only the rows themselves are allocated
+    let mut content = String::new();
+    reader.read_to_string(&mut content)?;
+    let mut lines = content.lines()
+        .filter(|it| !it.is_empty())
+        .map(|line| line.split('\t').collect::<Vec<_>>());
+    let mut header = match lines.next() {
+        Some(header) => header,
+        None => return Ok(()),
+    };
+    let mut rows = lines.collect::<Vec<_>>();
+
+    // A hand-edited summary may have rows with fewer or more cells than the header, the short ones are padded with
+    // empty cells. When the widths are measured on every row, all of them are padded to the longest one.
+    let width = match options.widths {
+        ColumnWidths::Measured => rows.iter().map(Vec::len).max().unwrap_or(0).max(header.len()),
+        _ => header.len(),
+    };
+    let ragged = rows.iter().filter(|it| it.len() != header.len()).count();
+    if ragged > 0 {
+        warn!("{} row(s) of the summary do not have as many cells as its header", ragged);
+    }
+    header.resize(width, "");
+    for row in rows.iter_mut().filter(|it| it.len() < width) {
+        row.resize(width, "");
+    }
+
+    for filter in &options.filters {
+        let index = column_index(&header, &filter.column)?;
+        rows.retain(|row| filter.matches(row[index]));
+    }
+
+    if let Some(sort_columns) = &options.sort_columns {
+        let keys = sort_columns.iter()
+            .filter_map(|key| header.iter().position(|it| it.eq_ignore_ascii_case(&key.column)).map(|index| (index, key)))
+            .collect::<Vec<_>>();
+        // The sort is stable, so the rows equal on every key keep the order of the file
+        rows.sort_by(|lhs, rhs| {
+            keys.iter()
+                .map(|(index, key)| key.compare(lhs[*index], rhs[*index]))
+                .find(|it| *it != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    if let Some(head) = options.head {
+        rows.truncate(head);
+    }
+    if let Some(tail) = options.tail {
+        rows.drain(..rows.len().saturating_sub(tail));
+    }
+
+    if let Some(columns) = &options.columns {
+        let indices = columns.iter()
+            .map(|column| column_index(&header, column))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        header = indices.iter().map(|&index| header[index]).collect();
+        for row in rows.iter_mut() {
+            *row = indices.iter().map(|&index| row[index]).collect();
+        }
+    }
+
+    let measured = match options.widths {
+        ColumnWidths::Measured => rows.len(),
+        ColumnWidths::Sampled(sample) => sample,
+        ColumnWidths::Assumed(_) => 0,
+    };
+    let mut col_sizes = header.iter().map(|it| it.len()).collect::<Vec<_>>();
+    for row in rows.iter().take(measured) {
+        measure(&mut col_sizes, row);
+    }
+    if let ColumnWidths::Assumed(width) = options.widths {
+        for size in col_sizes.iter_mut() {
+            *size = (*size).max(width);
+        }
+    }
+
+    let status_index = header.iter()
+        .position(|it| it.eq_ignore_ascii_case("status"))
+        .filter(|_| options.color);
+    print_row(out, header.iter().copied(), &col_sizes, None)?;
+    for row in &rows {
+        print_row(out, row.iter().copied(), &col_sizes, status_index)?;
+    }
+    if options.stats {
+        print_stats(out, &header, &rows)?;
+    }
+    out.flush()
+}
+
+/// Prints the summary file like `print_summary`. Unless its rows are sorted or their statistics computed,
+/// they are not held in memory: a first pass over the file measures the columns and a second one prints the rows.
+/// With `tail`, only the rows that may be printed are kept. With assumed or sampled widths, the file is read once.
+pub fn print_summary_file(path: &Path, options: &SummaryOptions) -> std::io::Result<()> {
+    write_summary_file(path, options, &mut BufWriter::new(std::io::stderr().lock()))
+}
+
+fn write_summary_file<W: Write>(path: &Path, options: &SummaryOptions, out: &mut W) -> std::io::Result<()> {
+    let file = File::open(path)?;
+    if options.sort_columns.is_some() || options.stats {
+        return write_summary(&mut BufReader::new(file), options, out);
+    }
+
+    // Both passes read the rows that were in the file when it was opened, from the same file, even if a run
+    // appends rows to the summary or replaces it in the meantime (e.g. with `--watch`)
+    let len = file.metadata()?.len();
+    let mut lines = summary_lines((&file).take(len));
+    let header = match lines.next() {
+        Some(header) => header?,
+        None => return Ok(()),
+    };
+    let header = header.split('\t').map(String::from).collect::<Vec<_>>();
+    let filters = options.filters.iter()
+        .map(|filter| Ok((column_index(&header, &filter.column)?, filter)))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let columns = match &options.columns {
+        Some(columns) => Some(columns.iter()
+            .map(|column| column_index(&header, column))
+            .collect::<std::io::Result<Vec<_>>>()?),
+        None => None,
+    };
+    let status_index = match &columns {
+        Some(indices) => indices.iter().position(|&index| header[index].eq_ignore_ascii_case("status")),
+        None => header.iter().position(|it| it.eq_ignore_ascii_case("status")),
+    }.filter(|_| options.color);
+    let header_cells = header.iter().map(String::as_str).collect::<Vec<_>>();
+    let mut rows = ShownRows::new(lines, &header, &filters, options.head);
+
+    if let Some(tail) = options.tail {
+        let mut last_rows = VecDeque::new();
+        for line in rows.by_ref() {
+            if last_rows.len() == tail {
+                last_rows.pop_front();
+            }
+            if tail > 0 {
+                last_rows.push_back(line?);
+            }
+        }
+        rows.warn_ragged();
+        let (indices, sample) = match options.widths {
+            ColumnWidths::Measured => (columns.or_else(|| Some((0..rows.width).collect())), last_rows.len()),
+            ColumnWidths::Sampled(sample) => (columns, sample),
+            ColumnWidths::Assumed(_) => (columns, 0),
+        };
+        let col_sizes = column_sizes(&header_cells, last_rows.iter().take(sample), options.widths, indices.as_deref());
+        print_row(out, select(&header_cells, indices.as_deref()), &col_sizes, None)?;
+        for line in &last_rows {
+            print_row(out, select(&cells(line, header.len()), indices.as_deref()), &col_sizes, status_index)?;
+        }
+        return out.flush();
+    }
+
+    if options.widths == ColumnWidths::Measured {
+        let mut col_sizes = header.iter().map(String::len).collect::<Vec<_>>();
+        for line in rows.by_ref() {
+            measure(&mut col_sizes, &line?.split('\t').collect::<Vec<_>>());
+        }
+        rows.warn_ragged();
+        col_sizes.resize(rows.width, 0);
+        let indices = columns.unwrap_or_else(|| (0..rows.width).collect());
+        let col_sizes = indices.iter().map(|&index| col_sizes[index]).collect::<Vec<_>>();
+
+        print_row(out, select(&header_cells, Some(&indices)), &col_sizes, None)?;
+        (&file).seek(SeekFrom::Start(0))?;
+        let lines = summary_lines((&file).take(len)).skip(1);
+        for line in ShownRows::new(lines, &header, &filters, options.head) {
+            print_row(out, select(&line?.split('\t').collect::<Vec<_>>(), Some(&indices)), &col_sizes, status_index)?;
+        }
+        return out.flush();
+    }
+
+    // The columns are measured on the first rows only, the following ones are printed as they are read
+    let sample = match options.widths {
+        ColumnWidths::Sampled(sample) => sample,
+        _ => 0,
+    };
+    let mut first_rows = Vec::new();
+    for line in rows.by_ref().take(sample) {
+        first_rows.push(line?);
+    }
+    let col_sizes = column_sizes(&header_cells, first_rows.iter(), options.widths, columns.as_deref());
+    print_row(out, select(&header_cells, columns.as_deref()), &col_sizes, None)?;
+    for line in &first_rows {
+        print_row(out, select(&cells(line, header.len()), columns.as_deref()), &col_sizes, status_index)?;
+    }
+    for line in rows.by_ref() {
+        print_row(out, select(&cells(&line?, header.len()), columns.as_deref()), &col_sizes, status_index)?;
+    }
+    out.flush()?;
+    rows.warn_ragged();
+    Ok(())
+}
+
+/// Rows of a summary file matching the filters, up to `head` of them.
+/// The rows that are not shown are still read to count the ones whose length differs from the header.
+struct ShownRows<'a, I> {
+    lines: I,
+    header_len: usize,
+    filters: &'a [(usize, &'a Filter)],
+    remaining: usize,
+    ragged: usize,
+    /// Number of cells of the longest row
+    width: usize,
+}
+
+impl<'a, I: Iterator<Item=std::io::Result<String>>> ShownRows<'a, I> {
+    fn new(lines: I, header: &[String], filters: &'a [(usize, &'a Filter)], head: Option<usize>) -> Self {
+        ShownRows { lines, header_len: header.len(), filters, remaining: head.unwrap_or(usize::MAX), ragged: 0, width: header.len() }
+    }
+
+    fn warn_ragged(&self) {
+        if self.ragged > 0 {
+            warn!("{} row(s) of the summary do not have as many cells as its header", self.ragged);
+        }
+    }
+}
+
+impl<I: Iterator<Item=std::io::Result<String>>> Iterator for ShownRows<'_, I> {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for line in self.lines.by_ref() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let cells = line.split('\t').collect::<Vec<_>>();
+            if cells.len() != self.header_len {
+                self.ragged += 1;
+            }
+            self.width = self.width.max(cells.len());
+            let is_shown = self.filters.iter()
+                .all(|(index, filter)| filter.matches(cells.get(*index).copied().unwrap_or("")));
+            if self.remaining == 0 || !is_shown {
+                continue;
+            }
+            self.remaining -= 1;
+            return Some(Ok(line));
+        }
+        None
+    }
+}
+
+/// Widths of the displayed columns, measured on the header and `rows`, at least the assumed width if any
+fn column_sizes<'a>(header: &[&str], rows: impl Iterator<Item=&'a String>, widths: ColumnWidths, indices: Option<&[usize]>) -> Vec<usize> {
+    let mut col_sizes = header.iter().map(|it| it.len()).collect::<Vec<_>>();
+    for row in rows {
+        measure(&mut col_sizes, &row.split('\t').collect::<Vec<_>>());
+    }
+    if let ColumnWidths::Assumed(width) = widths {
+        for size in col_sizes.iter_mut() {
+            *size = (*size).max(width);
+        }
+    }
+    match indices {
+        Some(indices) => indices.iter().map(|&index| col_sizes.get(index).copied().unwrap_or(0)).collect(),
+        None => col_sizes,
+    }
+}
+
+fn measure(col_sizes: &mut Vec<usize>, cells: &[&str]) {
+    if cells.len() > col_sizes.len() {
+        col_sizes.resize(cells.len(), 0);
+    }
+    for (size, cell) in col_sizes.iter_mut().zip(cells) {
+        *size = (*size).max(cell.len());
+    }
+}
+
+/// The cells of a row, padded with empty cells up to `len`
+fn cells(line: &str, len: usize) -> Vec<&str> {
+    let mut cells = line.split('\t').collect::<Vec<_>>();
+    if cells.len() < len {
+        cells.resize(len, "");
+    }
+    cells
+}
+
+/// The cells at `indices`, empty past the end of the row, or all of them
+fn select<'a, 'b>(cells: &'b [&'a str], indices: Option<&'b [usize]>) -> Box<dyn Iterator<Item=&'a str> + 'b> {
+    match indices {
+        Some(indices) => Box::new(indices.iter().map(move |&index| cells.get(index).copied().unwrap_or(""))),
+        None => Box::new(cells.iter().copied()),
+    }
+}
+
+/// The non-empty lines of a summary
+fn summary_lines<RS: Read>(reader: RS) -> impl Iterator<Item=std::io::Result<String>> {
+    BufReader::new(reader).lines()
+        .filter(|line| line.as_ref().map_or(true, |it| !it.is_empty()))
+}
+
+/// Prints the cells of a row padded to `col_sizes`, the one at `status_index` colored after its value
+fn print_row<'a, W: Write>(out: &mut W, cells: impl Iterator<Item=&'a str>, col_sizes: &[usize], status_index: Option<usize>) -> std::io::Result<()> {
+    for (i, part) in cells.enumerate() {
+        let width = col_sizes.get(i).copied().unwrap_or(0) + 3;
+        if Some(i) == status_index {
+            // Padded before being colored so that the escape codes do not count in the width
+            write!(out, "{}", colored_status(part, &format!("{:1$}", part, width)))?;
+        } else {
+            write!(out, "{:1$}", part, width)?;
+        }
+    }
+    writeln!(out)
+}
+
+/// Redraws the summary file every `interval`, e.g. while a run is in progress in another terminal,
+/// until the process is interrupted
+pub fn watch_summary(path: &Path, options: &SummaryOptions, interval: Duration) -> std::io::Result<()> {
+    loop {
+        // Clears the screen and moves the cursor to its top left corner
+        eprint!("\x1b[2J\x1b[H");
+        eprintln!(
+            "Every {}: {}    {}\n",
+            humantime::format_duration(interval), path.display(), chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        );
+        match print_summary_file(path, options) {
+            Err(e) if e.kind() == ErrorKind::NotFound => eprintln!("Waiting for the summary file to be created..."),
+            result => result?,
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn colored_status(status: &str, cell: &str) -> ColoredString {
+    match status {
+        "Ok" => cell.green(),
+        "Timeout" => cell.yellow(),
+        "Skipped" => cell.cyan(),
+        "" => cell.normal(),
+        _ => cell.red(),
+    }
+}
+
+fn column_index<S: AsRef<str>>(header: &[S], column: &str) -> std::io::Result<usize> {
+    header.iter()
+        .position(|it| it.as_ref().eq_ignore_ascii_case(column))
+        .ok_or_else(|| Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unknown column '{}'. Available columns are: {}", column, header.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(", ")),
+        ))
+}
+
+pub fn compare_cells(lhs: &str, rhs: &str) -> Ordering {
+    match (lhs.parse::<humantime::Duration>(), rhs.parse::<humantime::Duration>()) {
+        (Ok(lhs), Ok(rhs)) => lhs.cmp(&rhs),
+        _ => human_sort::compare(lhs, rhs),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ColumnKind { Number, Duration }
+
+impl ColumnKind {
+    pub fn parse(&self, cell: &str) -> Option<f64> {
+        match self {
+            ColumnKind::Number => cell.parse::<f64>().ok(),
+            ColumnKind::Duration => cell.parse::<humantime::Duration>()
+                .ok()
+                .map(|it| it.as_secs_f64()),
+        }
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        match self {
+            ColumnKind::Number => format!("{:.3}", value)
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_owned(),
+            ColumnKind::Duration => {
+                let millis = Duration::from_millis((value * 1000.0).round() as u64);
+                humantime::format_duration(millis).to_string()
+            }
+        }
+    }
+}
+
+/// Detects whether every non-empty cell of the column holds a number or a duration.
+/// Columns without any value are not considered numeric.
+pub fn column_kind<'a, I>(cells: I) -> Option<ColumnKind>
+    where I: Iterator<Item=&'a str> + Clone {
+    let mut cells = cells.filter(|it| !it.trim().is_empty()).peekable();
+    cells.peek()?;
+    [ColumnKind::Number, ColumnKind::Duration].iter().copied()
+        .find(|kind| cells.clone().all(|cell| kind.parse(cell.trim()).is_some()))
+}
+
+fn print_stats<W: Write>(out: &mut W, header: &[&str], rows: &[Vec<&str>]) -> std::io::Result<()> {
+    let mut table = vec![
+        ["column", "min", "max", "mean", "median", "sum"].map(String::from).to_vec()
+    ];
+
+    for (index, column) in header.iter().enumerate() {
+        let cells = rows.iter().map(|row| row.get(index).copied().unwrap_or(""));
+        let kind = match column_kind(cells.clone()) {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        let mut values = cells.filter_map(|cell| kind.parse(cell.trim())).collect::<Vec<_>>();
+        values.sort_by(f64::total_cmp);
+
+        let sum = values.iter().sum::<f64>();
+        let mean = sum / values.len() as f64;
+        let middle = values.len() / 2;
+        let median = if values.len() % 2 == 0 {
+            (values[middle - 1] + values[middle]) / 2.0
+        } else {
+            values[middle]
+        };
+
+        table.push(vec![
+            column.to_string(),
+            kind.format(values[0]),
+            kind.format(values[values.len() - 1]),
+            kind.format(mean),
+            kind.format(median),
+            kind.format(sum),
+        ]);
+    }
+
+    if table.len() == 1 {
+        return writeln!(out, "No numeric column to compute statistics on.");
+    }
+
+    let col_sizes = (0..table[0].len())
+        .map(|i| table.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+
+    writeln!(out)?;
+    for row in &table {
+        for (i, cell) in row.iter().enumerate() {
+            write!(out, "{:1$}", cell, col_sizes[i] + 3)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::BufReader;
+    use super::{write_summary, write_summary_file, ColumnWidths, Filter, SummaryOptions};
+
+    const SUMMARY: &str = "name\tstatus\ttime\titeration\n\
+        queens_8\tOk\t1s 200ms\t1/1\n\
+        queens_16\tTimeout\t1m\t1/1\n\
+        \n\
+        golomb_10\tOk\t45s\t1/1\n\
+        golomb_12\tError\t3s\n\
+        sudoku\tOk\t80ms\t1/1\tedited by hand\n\
+        magic_square\tOk\t2s 500ms\t1/1\n";
+
+    fn options() -> SummaryOptions {
+        SummaryOptions {
+            sort_columns: None,
+            filters: Vec::new(),
+            columns: None,
+            stats: false,
+            head: None,
+            tail: None,
+            color: false,
+            widths: ColumnWidths::Measured,
+        }
+    }
+
+    /// The summary printed from memory then streamed from a file with the same options
+    fn printed(options: &SummaryOptions) -> (String, String) {
+        let path = std::env::temp_dir().join(format!("whitesmith-print-{}-{:?}.csv", std::process::id(), std::thread::current().id()));
+        fs::write(&path, SUMMARY).unwrap();
+        let mut from_memory = Vec::new();
+        write_summary(&mut BufReader::new(SUMMARY.as_bytes()), options, &mut from_memory).unwrap();
+        let mut from_file = Vec::new();
+        let result = write_summary_file(&path, options, &mut from_file);
+        fs::remove_file(&path).unwrap();
+        result.unwrap();
+        (String::from_utf8(from_memory).unwrap(), String::from_utf8(from_file).unwrap())
+    }
+
+    #[test]
+    fn a_summary_file_is_printed_like_the_summary() {
+        let filter = |it: &str| it.parse::<Filter>().unwrap();
+        let cases = vec![
+            options(),
+            SummaryOptions { head: Some(2), ..options() },
+            SummaryOptions { head: Some(0), ..options() },
+            SummaryOptions { tail: Some(2), ..options() },
+            SummaryOptions { tail: Some(0), ..options() },
+            SummaryOptions { head: Some(4), tail: Some(2), ..options() },
+            SummaryOptions { filters: vec![filter("status=Ok")], ..options() },
+            SummaryOptions { filters: vec![filter("status!=ok"), filter("time>2s")], ..options() },
+            SummaryOptions { columns: Some(vec![String::from("time"), String::from("NAME")]), ..options() },
+            SummaryOptions { filters: vec![filter("status=Ok")], head: Some(2), columns: Some(vec![String::from("name")]), ..options() },
+            SummaryOptions { filters: vec![filter("status=Ok")], tail: Some(1), columns: Some(vec![String::from("iteration")]), ..options() },
+            SummaryOptions { widths: ColumnWidths::Sampled(2), ..options() },
+            SummaryOptions { widths: ColumnWidths::Assumed(4), tail: Some(3), ..options() },
+        ];
+        for (i, case) in cases.iter().enumerate() {
+            let (from_memory, from_file) = printed(case);
+            assert_eq!(from_memory, from_file, "case {}", i);
+        }
+    }
+
+    #[test]
+    fn ragged_rows_are_padded_to_the_longest_one() {
+        let (from_memory, from_file) = printed(&options());
+        assert_eq!(from_memory, from_file);
+
+        let lines = from_file.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[4].starts_with("golomb_12"));
+        assert!(lines[5].trim_end().ends_with("edited by hand"));
+        assert!(lines.iter().all(|line| line.len() == lines[5].len()));
+    }
+
+    #[test]
+    fn unknown_columns_are_refused() {
+        let path = std::env::temp_dir().join(format!("whitesmith-unknown-{}.csv", std::process::id()));
+        fs::write(&path, SUMMARY).unwrap();
+        let options = SummaryOptions { columns: Some(vec![String::from("objective")]), ..options() };
+        let result = write_summary_file(&path, &options, &mut Vec::new());
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap_err().to_string().starts_with("Unknown column 'objective'"));
+    }
+}
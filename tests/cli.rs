@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory of its own holding the configuration `name.ron` of the computations `experiments`, emptied first
+fn configuration(name: &str, experiments: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("whitesmith-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let configuration = directory.join(format!("{}.ron", name));
+    fs::write(&configuration, format!(r#"(
+    version: ({}),
+    versioning: (url: "file:/dev/null"),
+    commands: (build: "true"),
+    experiments: [{}],
+)"#, env!("CARGO_PKG_VERSION").replace('.', ", "), experiments)).unwrap();
+    configuration
+}
+
+/// Runs `whitesmith <options> <configuration> <action>`, which must succeed
+fn whitesmith(options: &[&str], configuration: &Path, action: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_whitesmith"))
+        .arg("--quiet")
+        .args(options)
+        .arg(configuration)
+        .args(action)
+        .status()
+        .unwrap();
+    assert!(status.success(), "whitesmith {:?} failed", action);
+}
+
+#[test]
+fn a_selective_clean_keeps_the_temporary_working_directory() {
+    let configuration = configuration("selective", r#"
+        (name: "a", cmd: "true"),
+        (name: "b", cmd: "true"),
+    "#);
+    let directory = configuration.parent().unwrap().to_path_buf();
+    let temp_dir = format!("--temp-dir={}", directory.join("tmp").display());
+
+    whitesmith(&[&temp_dir], &configuration, &["run"]);
+    whitesmith(&[&temp_dir], &configuration, &["clean", "--only", "a", "--no-backup"]);
+    let logs = glob::glob(&format!("{}/tmp/whitesmith/*/logs/*", directory.display())).unwrap()
+        .map(|it| it.unwrap().file_name().unwrap().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+
+    whitesmith(&[&temp_dir], &configuration, &["clean", "--no-backup"]);
+    let remaining = fs::read_dir(directory.join("tmp/whitesmith")).unwrap().count();
+    let _ = fs::remove_dir_all(&directory);
+
+    assert_eq!(logs, ["b"]);
+    assert_eq!(remaining, 0);
+}
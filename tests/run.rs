@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use whitesmith::{open_project, ComputationResult, Project, RunOptions, RunReport, RunState};
+
+/// Project of the computations `experiments`, in a directory of its own that is emptied first
+fn project(name: &str, nb_threads: usize, global_timeout: &str, experiments: &str) -> Project {
+    let directory = std::env::temp_dir().join(format!("whitesmith-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    let configuration = directory.join(format!("{}.ron", name));
+    fs::write(&configuration, format!(r#"(
+    version: ({}),
+    versioning: (url: "file:/dev/null"),
+    commands: (build: "true"),
+    nb_threads: Some({}),
+    global_timeout: {},
+    experiments: [{}],
+)"#, env!("CARGO_PKG_VERSION").replace('.', ", "), nb_threads, global_timeout, experiments)).unwrap();
+
+    let project = open_project(&configuration);
+    // Nothing to fetch, the computations only need their directories
+    fs::create_dir_all(&project.source_directory).unwrap();
+    fs::create_dir_all(&project.log_directory).unwrap();
+    project
+}
+
+fn run(project: &Project, state: &Arc<RunState>) -> RunReport {
+    whitesmith::run(Arc::new(project.clone()), &RunOptions::default(), state)
+        .expect("The run cannot start")
+}
+
+fn clean(project: &Project) {
+    let directory = PathBuf::from(&project.working_directory);
+    let _ = fs::remove_dir_all(directory.parent().unwrap());
+}
+
+fn status(report: &RunReport, name: &str) -> ComputationResult {
+    report.results.iter()
+        .find(|(it, _)| it == name)
+        .map(|(_, result)| *result)
+        .unwrap_or_else(|| panic!("{} has no result", name))
+}
+
+#[test]
+fn computations_report_their_status() {
+    let project = project("status", 1, "None", r#"
+        (name: "ok", cmd: "true"),
+        (name: "error", cmd: "exit 3"),
+    "#);
+    let report = run(&project, &Arc::new(RunState::default()));
+    clean(&project);
+
+    assert_eq!(report.results.len(), 2);
+    assert!(matches!(status(&report, "ok"), ComputationResult::Ok(..)));
+    assert!(matches!(status(&report, "error"), ComputationResult::Error(..)));
+    assert!(!report.is_success());
+    assert_eq!(report.failures().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["error"]);
+}
+
+#[test]
+fn computations_exceeding_the_timeout_are_killed() {
+    let project = project("timeout", 1, r#"Some("1s")"#, r#"
+        (name: "slow", cmd: "sleep 30"),
+        (name: "fast", cmd: "sleep 0.1"),
+    "#);
+    let state = Arc::new(RunState::default());
+    let clock = Instant::now();
+    let report = run(&project, &state);
+    clean(&project);
+
+    assert!(clock.elapsed() < Duration::from_secs(20));
+    assert!(matches!(status(&report, "slow"), ComputationResult::Timeout(..)));
+    assert!(matches!(status(&report, "fast"), ComputationResult::Ok(..)));
+    assert!(state.children.snapshot().is_empty());
+}
+
+#[test]
+fn abort_kills_the_children_and_starts_no_other_computation() {
+    let project = project("abort", 2, "None", r#"
+        (
+            foreach: { "I": [1, 2, 3, 4, 5, 6] },
+            apply: (aliases: {}, cmds: [ (name: "sleep_{I}", cmd: "sleep 30") ]),
+        ),
+    "#);
+    let state = Arc::new(RunState::default());
+
+    // Simulates the second Ctrl-C once both threads run a computation
+    let interrupt = state.clone();
+    let interrupter = thread::spawn(move || {
+        let clock = Instant::now();
+        while interrupt.children.snapshot().len() < 2 {
+            assert!(clock.elapsed() < Duration::from_secs(10), "The computations do not start");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!interrupt.abort());
+        interrupt.kill_children();
+    });
+
+    let clock = Instant::now();
+    let report = run(&project, &state);
+    interrupter.join().unwrap();
+    clean(&project);
+
+    assert!(clock.elapsed() < Duration::from_secs(20));
+    assert!(state.is_aborted());
+    assert!(state.children.snapshot().is_empty());
+    assert_eq!(report.results.len(), 2);
+    assert!(report.results.iter().all(|(_, result)| matches!(result, ComputationResult::Error(..))));
+}
+
+#[test]
+fn a_process_runs_several_projects_one_after_the_other() {
+    for name in ["first", "second"] {
+        let project = project(name, 2, "None", r#"
+            (name: "ok", cmd: "true"),
+            (name: "error", cmd: "false"),
+        "#);
+        let report = run(&project, &Arc::new(RunState::default()));
+        clean(&project);
+
+        assert_eq!(report.results.len(), 2);
+        assert_eq!(report.failures().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["error"]);
+    }
+}
+
+#[test]
+fn a_run_that_cannot_start_returns_why() {
+    let project = project("duplicates", 1, "None", r#"
+        (name: "twice", cmd: "true"),
+        (name: "twice", cmd: "true"),
+    "#);
+    let result = whitesmith::run(Arc::new(project.clone()), &RunOptions::default(), &Arc::new(RunState::default()));
+    clean(&project);
+
+    let error = result.expect_err("The run started");
+    assert!(error.starts_with("2 computations are named twice"), "{}", error);
+}
+
+#[test]
+fn skipped_computations_keep_their_logs_and_a_single_row() {
+    let project = project("skipped", 1, "None", r#"
+        (name: "gpu", cmd: "true", when: "echo no gpu here; false"),
+        (name: "after_gpu", cmd: "true", depends_on: ["gpu"]),
+    "#);
+    let state = Arc::new(RunState::default());
+    run(&project, &state);
+    let report = run(&project, &state);
+    let summary = fs::read_to_string(&project.summary_file).unwrap();
+    let when_log = fs::read_to_string(PathBuf::from(&project.log_directory).join("gpu/gpu_1.out"));
+    clean(&project);
+
+    assert!(matches!(status(&report, "gpu"), ComputationResult::Skipped(..)));
+    assert!(matches!(status(&report, "after_gpu"), ComputationResult::Skipped(..)));
+    let names = summary.lines().skip(1)
+        .map(|line| line.split('\t').next().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["gpu", "after_gpu"]);
+    assert_eq!(when_log.unwrap(), "no gpu here\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pinned_computations_leave_the_calling_thread_unpinned() {
+    fn allowed_cpus() -> i32 {
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            assert_eq!(libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+            libc::CPU_COUNT(&set)
+        }
+    }
+
+    let mut project = project("pinned", 2, "None", r#"
+        (name: "first", cmd: "true"),
+        (name: "second", cmd: "true"),
+    "#);
+    project.pin_cpus = true;
+    let before = allowed_cpus();
+    let report = run(&project, &Arc::new(RunState::default()));
+    clean(&project);
+
+    assert!(report.is_success());
+    assert_eq!(allowed_cpus(), before);
+}